@@ -0,0 +1,588 @@
+use std::{
+    fmt,
+    path::Path,
+};
+use crate::{
+    gamepad::{GamepadAxis, GamepadButton},
+    input::{Input, Key, Modifiers, MouseButton},
+};
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum Button {
+    Key(Key),
+    Mouse(MouseButton),
+    /// Matches the button on any connected gamepad.
+    Gamepad(GamepadButton),
+}
+
+impl From<Key> for Button {
+    fn from(key: Key) -> Button {
+        Button::Key(key)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Button {
+        Button::Mouse(button)
+    }
+}
+
+impl From<GamepadButton> for Button {
+    fn from(button: GamepadButton) -> Button {
+        Button::Gamepad(button)
+    }
+}
+
+/// A button together with the modifier keys that must be held for it to
+/// count, either side of the keyboard. E.g. `Binding::new(Key::S).ctrl()`.
+///
+/// A binding without modifiers is active regardless of which modifiers are
+/// held, so that e.g. running with shift does not stop movement.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct Binding {
+    pub button: Button,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Binding {
+    pub fn new(button: impl Into<Button>) -> Binding {
+        Binding {
+            button: button.into(),
+            shift: false,
+            ctrl: false,
+            alt: false,
+            logo: false,
+        }
+    }
+
+    pub fn shift(mut self) -> Binding {
+        self.shift = true;
+        self
+    }
+
+    pub fn ctrl(mut self) -> Binding {
+        self.ctrl = true;
+        self
+    }
+
+    pub fn alt(mut self) -> Binding {
+        self.alt = true;
+        self
+    }
+
+    pub fn logo(mut self) -> Binding {
+        self.logo = true;
+        self
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            shift: self.shift,
+            ctrl: self.ctrl,
+            alt: self.alt,
+            logo: self.logo,
+        }
+    }
+
+    pub fn is_active(&self, input: &Input) -> bool {
+        let pressed = match self.button {
+            Button::Key(key) => input.is_pressed(key),
+            Button::Mouse(button) => input.is_mouse_pressed(button),
+            Button::Gamepad(button) => input.gamepads().any(|(_, pad)| pad.is_pressed(button)),
+        };
+        pressed && (self.modifiers().is_empty() || input.modifiers() == self.modifiers())
+    }
+
+    /// Whether pressing `other`, with exactly its modifiers held, activates
+    /// this binding.
+    fn fires_on(&self, other: &Binding) -> bool {
+        self.button == other.button
+            && (self.modifiers().is_empty() || self.modifiers() == other.modifiers())
+    }
+}
+
+impl From<Button> for Binding {
+    fn from(button: Button) -> Binding {
+        Binding::new(button)
+    }
+}
+
+impl From<Key> for Binding {
+    fn from(key: Key) -> Binding {
+        Binding::new(key)
+    }
+}
+
+impl From<MouseButton> for Binding {
+    fn from(button: MouseButton) -> Binding {
+        Binding::new(button)
+    }
+}
+
+impl From<GamepadButton> for Binding {
+    fn from(button: GamepadButton) -> Binding {
+        Binding::new(button)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AxisBinding {
+    /// Reads `-1.0` while `negative` is held and `1.0` while `positive` is.
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    /// Reads the axis of any connected gamepad, dead zones applied.
+    Gamepad(GamepadAxis),
+}
+
+impl AxisBinding {
+    pub fn buttons(negative: impl Into<Binding>, positive: impl Into<Binding>) -> AxisBinding {
+        AxisBinding::Buttons {
+            negative: negative.into(),
+            positive: positive.into(),
+        }
+    }
+
+    pub fn value(&self, input: &Input) -> f32 {
+        match *self {
+            AxisBinding::Buttons { negative, positive } => {
+                let mut value = 0.0;
+                if negative.is_active(input) {
+                    value -= 1.0;
+                }
+                if positive.is_active(input) {
+                    value += 1.0;
+                }
+                value
+            }
+            AxisBinding::Gamepad(axis) => {
+                // the pad pushed furthest wins
+                input
+                    .gamepads()
+                    .map(|(_, pad)| pad.axis(axis))
+                    .fold(0.0, |acc: f32, v| if v.abs() > acc.abs() { v } else { acc })
+            }
+        }
+    }
+}
+
+/// Several actions that all fire when `binding` is pressed. This includes
+/// bindings without modifiers on the same button, which are active whatever
+/// modifiers are held.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Conflict {
+    pub binding: Binding,
+    pub actions: Vec<String>,
+}
+
+/// Named actions and axes bound to keys, mouse and gamepad buttons.
+///
+/// Names have to survive `to_config`: they can't be empty, start or end
+/// with whitespace, start with `#` or `[`, or contain `=`, `,` or line
+/// breaks. Binding such a name panics.
+#[derive(Debug, Default, Clone)]
+pub struct ActionMap {
+    actions: Vec<(String, Vec<Binding>)>,
+    axes: Vec<(String, Vec<AxisBinding>)>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap::default()
+    }
+
+    pub fn bind(&mut self, action: &str, binding: impl Into<Binding>) {
+        let binding = binding.into();
+        let bindings = entry(&mut self.actions, action);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: impl Into<Binding>) {
+        let binding = binding.into();
+        entry(&mut self.actions, action).retain(|&b| b != binding);
+    }
+
+    /// Removes every binding of the action, e.g. before assigning a new one.
+    pub fn clear(&mut self, action: &str) {
+        entry(&mut self.actions, action).clear();
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        find(&self.actions, action)
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        let bindings = entry(&mut self.axes, axis);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn clear_axis(&mut self, axis: &str) {
+        entry(&mut self.axes, axis).clear();
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        find(&self.axes, axis)
+    }
+
+    pub fn is_active(&self, input: &Input, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| b.is_active(input))
+    }
+
+    /// Sum of all bindings of the axis, clamped to `-1.0..=1.0`.
+    pub fn axis(&self, input: &Input, axis: &str) -> f32 {
+        let sum: f32 = self.axis_bindings(axis).iter().map(|b| b.value(input)).sum();
+        sum.clamp(-1.0, 1.0)
+    }
+
+    /// Actions other than `action` that would fire together with `binding`,
+    /// e.g. `Space` and `Ctrl+Space` conflict but `Ctrl+S` and `Alt+S` don't.
+    pub fn conflicts_with(&self, action: &str, binding: impl Into<Binding>) -> Vec<&str> {
+        let binding = binding.into();
+        self.actions
+            .iter()
+            .filter(|(name, bindings)| {
+                name != action
+                    && bindings.iter().any(|b| b.fires_on(&binding) || binding.fires_on(b))
+            })
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::<Conflict>::new();
+        for &binding in self.actions.iter().flat_map(|(_, bindings)| bindings) {
+            if conflicts.iter().any(|c| c.binding == binding) {
+                continue;
+            }
+            let actions = self
+                .actions
+                .iter()
+                .filter(|(_, bindings)| bindings.iter().any(|b| b.fires_on(&binding)))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            if actions.len() > 1 {
+                conflicts.push(Conflict { binding, actions });
+            }
+        }
+        conflicts
+    }
+
+    /// Serialises the bindings into the format read by `ActionMap::parse`:
+    ///
+    /// ```text
+    /// [actions]
+    /// jump = Space, Pad:South
+    /// save = Ctrl+S
+    ///
+    /// [axes]
+    /// move_x = A/D, Pad:LeftStickX
+    /// ```
+    pub fn to_config(&self) -> String {
+        let mut out = String::from("[actions]\n");
+        for (name, bindings) in &self.actions {
+            let bindings = bindings.iter().map(format_binding).collect::<Vec<_>>();
+            out.push_str(&format!("{} = {}\n", name, bindings.join(", ")));
+        }
+        out.push_str("\n[axes]\n");
+        for (name, bindings) in &self.axes {
+            let bindings = bindings
+                .iter()
+                .map(|binding| match binding {
+                    AxisBinding::Buttons { negative, positive } => {
+                        format!("{}/{}", format_binding(negative), format_binding(positive))
+                    }
+                    AxisBinding::Gamepad(axis) => format!("Pad:{:?}", axis),
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&format!("{} = {}\n", name, bindings.join(", ")));
+        }
+        out
+    }
+
+    pub fn parse(config: &str) -> Result<ActionMap, BindingsError> {
+        enum Section {
+            None,
+            Actions,
+            Axes,
+        }
+
+        let mut map = ActionMap::new();
+        let mut section = Section::None;
+        for (idx, line) in config.lines().enumerate() {
+            let error = |message: String| BindingsError::Parse { line: idx + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line {
+                "[actions]" => section = Section::Actions,
+                "[axes]" => section = Section::Axes,
+                _ => {
+                    let eq = line.find('=').ok_or_else(|| error("expected `name = bindings`".to_owned()))?;
+                    let name = line[..eq].trim();
+                    check_name(name).map_err(error)?;
+                    let bindings = line[(eq + 1)..]
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|b| !b.is_empty());
+                    match section {
+                        Section::None => return Err(error("binding outside of a section".to_owned())),
+                        Section::Actions => {
+                            map.clear(name);
+                            for binding in bindings {
+                                map.bind(name, parse_binding(binding).map_err(error)?);
+                            }
+                        }
+                        Section::Axes => {
+                            map.clear_axis(name);
+                            for binding in bindings {
+                                map.bind_axis(name, parse_axis_binding(binding).map_err(error)?);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BindingsError> {
+        std::fs::write(path, self.to_config()).map_err(BindingsError::Io)
+    }
+
+    pub fn load(path: &Path) -> Result<ActionMap, BindingsError> {
+        let config = std::fs::read_to_string(path).map_err(BindingsError::Io)?;
+        ActionMap::parse(&config)
+    }
+}
+
+fn entry<'a, T>(list: &'a mut Vec<(String, Vec<T>)>, name: &str) -> &'a mut Vec<T> {
+    let idx = match list.iter().position(|(n, _)| n == name) {
+        Some(idx) => idx,
+        None => {
+            if let Err(e) = check_name(name) {
+                panic!("{}", e);
+            }
+            list.push((name.to_owned(), Vec::new()));
+            list.len() - 1
+        }
+    };
+    &mut list[idx].1
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.trim() == name
+        && !name.starts_with(&['#', '['][..])
+        && !name.contains(&['=', ',', '\n', '\r'][..]);
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not a valid action or axis name", name))
+    }
+}
+
+fn find<'a, T>(list: &'a [(String, Vec<T>)], name: &str) -> &'a [T] {
+    list.iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, bindings)| bindings.as_slice())
+        .unwrap_or(&[])
+}
+
+fn format_binding(binding: &Binding) -> String {
+    let mut out = String::new();
+    let modifiers = [
+        (binding.ctrl, "Ctrl"),
+        (binding.shift, "Shift"),
+        (binding.alt, "Alt"),
+        (binding.logo, "Logo"),
+    ];
+    for (held, name) in modifiers.iter() {
+        if *held {
+            out.push_str(name);
+            out.push('+');
+        }
+    }
+    match binding.button {
+        Button::Key(key) => out.push_str(&format!("{:?}", key)),
+        Button::Mouse(button) => out.push_str(&format!("Mouse:{:?}", button)),
+        Button::Gamepad(button) => out.push_str(&format!("Pad:{:?}", button)),
+    }
+    out
+}
+
+fn parse_binding(s: &str) -> Result<Binding, String> {
+    let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+    let name = parts.pop().unwrap();
+    let mut binding = Binding::new(parse_button(name)?);
+    for part in parts {
+        binding = match part {
+            "Ctrl" => binding.ctrl(),
+            "Shift" => binding.shift(),
+            "Alt" => binding.alt(),
+            "Logo" => binding.logo(),
+            _ => return Err(format!("unknown modifier `{}`", part)),
+        };
+    }
+    Ok(binding)
+}
+
+fn parse_button(button: &str) -> Result<Button, String> {
+    if let Some(name) = button.strip_prefix("Mouse:") {
+        match name {
+            "Left" => Ok(Button::Mouse(MouseButton::Left)),
+            "Right" => Ok(Button::Mouse(MouseButton::Right)),
+            _ => Err(format!("unknown mouse button `{}`", name)),
+        }
+    } else if let Some(name) = button.strip_prefix("Pad:") {
+        GamepadButton::ALL
+            .iter()
+            .copied()
+            .find(|b| format!("{:?}", b) == name)
+            .map(Button::Gamepad)
+            .ok_or_else(|| format!("unknown gamepad button `{}`", name))
+    } else {
+        Key::from_name(button)
+            .map(Button::Key)
+            .ok_or_else(|| format!("unknown key `{}`", button))
+    }
+}
+
+fn parse_axis_binding(s: &str) -> Result<AxisBinding, String> {
+    if let Some(slash) = s.find('/') {
+        let negative = parse_binding(&s[..slash])?;
+        let positive = parse_binding(&s[(slash + 1)..])?;
+        Ok(AxisBinding::Buttons { negative, positive })
+    } else if let Some(name) = s.strip_prefix("Pad:") {
+        GamepadAxis::ALL
+            .iter()
+            .copied()
+            .find(|a| format!("{:?}", a) == name)
+            .map(AxisBinding::Gamepad)
+            .ok_or_else(|| format!("unknown gamepad axis `{}`", name))
+    } else {
+        Err(format!("expected `negative/positive` or a gamepad axis, got `{}`", s))
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io(e) => write!(f, "failed to access bindings file: {}", e),
+            BindingsError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifier_less_bindings_conflict_with_modified_ones() {
+        let mut map = ActionMap::new();
+        map.bind("jump", Key::Space);
+        map.bind("dash", Binding::new(Key::Space).ctrl());
+        map.bind("save", Binding::new(Key::S).ctrl());
+        map.bind("screenshot", Binding::new(Key::S).alt());
+
+        assert_eq!(map.conflicts(), vec![Conflict {
+            binding: Binding::new(Key::Space).ctrl(),
+            actions: vec!["jump".to_owned(), "dash".to_owned()],
+        }]);
+        assert_eq!(map.conflicts_with("jump", Binding::new(Key::Space).shift()), Vec::<&str>::new());
+        assert_eq!(map.conflicts_with("save", Key::Space), vec!["jump", "dash"]);
+        assert_eq!(map.conflicts_with("save", Key::S), vec!["screenshot"]);
+        assert_eq!(map.conflicts_with("save", Binding::new(Key::S).ctrl()), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn same_binding_conflicts() {
+        let mut map = ActionMap::new();
+        map.bind("fire", MouseButton::Left);
+        map.bind("select", MouseButton::Left);
+        map.bind("jump", GamepadButton::South);
+        assert_eq!(map.conflicts(), vec![Conflict {
+            binding: Binding::new(MouseButton::Left),
+            actions: vec!["fire".to_owned(), "select".to_owned()],
+        }]);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let mut map = ActionMap::new();
+        map.bind("jump", Key::Space);
+        map.bind("jump", GamepadButton::South);
+        map.bind("save", Binding::new(Key::S).ctrl());
+        map.bind("everything", Binding::new(Key::F12).ctrl().shift().alt().logo());
+        map.bind("fire", MouseButton::Left);
+        map.clear("unbound");
+        map.bind_axis("move_x", AxisBinding::buttons(Key::A, Key::D));
+        map.bind_axis("move_x", AxisBinding::Gamepad(GamepadAxis::LeftStickX));
+        map.bind_axis("zoom", AxisBinding::buttons(Binding::new(Key::Minus).ctrl(), MouseButton::Right));
+
+        let config = map.to_config();
+        let parsed = ActionMap::parse(&config).unwrap();
+        for action in &["jump", "save", "everything", "fire", "unbound"] {
+            assert_eq!(parsed.bindings(action), map.bindings(action), "{}", action);
+        }
+        for axis in &["move_x", "zoom"] {
+            assert_eq!(parsed.axis_bindings(axis), map.axis_bindings(axis), "{}", axis);
+        }
+        assert_eq!(parsed.to_config(), config);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |config| match ActionMap::parse(config) {
+            Err(BindingsError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(err("jump = Space"), 1);
+        assert_eq!(err("[actions]\njump = Hyper+Space"), 2);
+        assert_eq!(err("[actions]\n\njump = Pad:Nope"), 3);
+        assert_eq!(err("[axes]\nmove_x = A"), 2);
+        assert_eq!(err("[actions]\n= Space"), 2);
+        assert_eq!(err("[axes]\nmove,x = A/D"), 2);
+    }
+
+    #[test]
+    fn names() {
+        let mut map = ActionMap::new();
+        map.bind("open map", Key::M);
+        map.bind("schießen", Key::Space);
+        map.bind("weapon #2", Key::Key2);
+        map.bind_axis("move [x]", AxisBinding::buttons(Key::A, Key::D));
+        let parsed = ActionMap::parse(&map.to_config()).unwrap();
+        for action in &["open map", "schießen", "weapon #2"] {
+            assert_eq!(parsed.bindings(action), map.bindings(action), "{}", action);
+        }
+        assert_eq!(parsed.axis_bindings("move [x]"), map.axis_bindings("move [x]"));
+
+        for &name in &["", " jump", "jump ", "#jump", "[actions]", "a=b", "a,b", "a\nb", "a\r"] {
+            let result = std::panic::catch_unwind(|| ActionMap::new().bind(name, Key::Space));
+            assert!(result.is_err(), "{:?} was accepted", name);
+            let result = std::panic::catch_unwind(|| ActionMap::new().clear_axis(name));
+            assert!(result.is_err(), "{:?} was accepted as an axis", name);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+use crate::{
+    Audio, Rect, Sound, Texture, TextureProcessors, Vfs,
+    decode::SoundData,
+    hot_reload::{HotReload, TextureSource},
+    texture::BindableTexture,
+};
+
+#[derive(Clone)]
+pub(crate) enum Asset {
+    Texture(PathBuf, TextureProcessors),
+    SpriteSheet(PathBuf, u32, u32),
+    Sound(PathBuf),
+    Data(PathBuf),
+}
+
+/// A group of assets that are loaded together with `JobQueue::load_assets`,
+/// e.g. everything a level needs.
+///
+/// Manifest files list assets by name in sections, with paths relative to
+/// the manifest:
+///
+/// ```text
+/// [textures]
+/// tiles = tiles.png
+/// [sprite_sheets]
+/// hero = 4x2 hero.png
+/// [sounds]
+/// jump = sfx/jump.wav
+/// [data]
+/// map = level3.txt
+/// ```
+///
+/// Sprite sheets are given as columns x rows.
+#[derive(Clone, Default)]
+pub struct Manifest {
+    pub(crate) assets: Vec<(String, Asset)>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    pub fn texture(self, name: &str, path: impl Into<PathBuf>) -> Manifest {
+        self.texture_with(name, path, TextureProcessors::new())
+    }
+
+    pub fn texture_with(
+        self,
+        name: &str,
+        path: impl Into<PathBuf>,
+        processors: TextureProcessors,
+    ) -> Manifest {
+        self.add(name, Asset::Texture(path.into(), processors))
+    }
+
+    /// A texture split into a grid of equally sized frames.
+    pub fn sprite_sheet(self, name: &str, path: impl Into<PathBuf>, columns: u32, rows: u32) -> Manifest {
+        assert!(columns > 0 && rows > 0, "sprite sheet needs at least one frame");
+        self.add(name, Asset::SpriteSheet(path.into(), columns, rows))
+    }
+
+    pub fn sound(self, name: &str, path: impl Into<PathBuf>) -> Manifest {
+        self.add(name, Asset::Sound(path.into()))
+    }
+
+    /// A file that is read as is, e.g. level data.
+    pub fn data(self, name: &str, path: impl Into<PathBuf>) -> Manifest {
+        self.add(name, Asset::Data(path.into()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Replaces an asset of the same kind and name, names only have to be
+    /// unique per kind as in `Assets`.
+    fn add(mut self, name: &str, asset: Asset) -> Manifest {
+        let kind = std::mem::discriminant(&asset);
+        self.assets.retain(|(n, a)| n != name || std::mem::discriminant(a) != kind);
+        self.assets.push((name.to_owned(), asset));
+        self
+    }
+
+    /// Parses a manifest, leaving paths as they are.
+    pub fn parse(manifest: &str) -> Result<Manifest, ManifestError> {
+        enum Section {
+            None,
+            Textures,
+            SpriteSheets,
+            Sounds,
+            Data,
+        }
+
+        let mut result = Manifest::new();
+        let mut section = Section::None;
+        for (idx, line) in manifest.lines().enumerate() {
+            let error = |message: &str| ManifestError::Parse { line: idx + 1, message: message.to_owned() };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line {
+                "[textures]" => section = Section::Textures,
+                "[sprite_sheets]" => section = Section::SpriteSheets,
+                "[sounds]" => section = Section::Sounds,
+                "[data]" => section = Section::Data,
+                _ => {
+                    let eq = line.find('=').ok_or_else(|| error("expected `name = path`"))?;
+                    let name = line[..eq].trim();
+                    let path = line[(eq + 1)..].trim();
+                    if name.is_empty() || path.is_empty() {
+                        return Err(error("expected `name = path`"));
+                    }
+                    result = match section {
+                        Section::None => return Err(error("asset outside of a section")),
+                        Section::Textures => result.texture(name, path),
+                        Section::SpriteSheets => {
+                            let (grid, path) = path.split_at(path.find(' ').unwrap_or(path.len()));
+                            let path = path.trim();
+                            let (columns, rows) = parse_grid(grid)
+                                .filter(|_| !path.is_empty())
+                                .ok_or_else(|| error("expected `name = COLUMNSxROWS path`"))?;
+                            result.sprite_sheet(name, path, columns, rows)
+                        }
+                        Section::Sounds => result.sound(name, path),
+                        Section::Data => result.data(name, path),
+                    };
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Loads a manifest file through `vfs`, usually `Ctx::vfs`, with paths
+    /// relative to the file.
+    pub fn load(vfs: &Vfs, path: &Path) -> Result<Manifest, ManifestError> {
+        let manifest = vfs.read(path).map_err(ManifestError::Io)?;
+        let manifest = String::from_utf8(manifest)
+            .map_err(|e| ManifestError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let mut manifest = Manifest::parse(&manifest)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for (_, asset) in &mut manifest.assets {
+            let path = match asset {
+                Asset::Texture(path, _) | Asset::SpriteSheet(path, _, _) => path,
+                Asset::Sound(path) | Asset::Data(path) => path,
+            };
+            *path = dir.join(&path);
+        }
+        Ok(manifest)
+    }
+}
+
+fn parse_grid(grid: &str) -> Option<(u32, u32)> {
+    let x = grid.find('x')?;
+    let columns = grid[..x].parse().ok()?;
+    let rows = grid[(x + 1)..].parse().ok()?;
+    if columns == 0 || rows == 0 {
+        return None;
+    }
+    Some((columns, rows))
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "failed to read manifest: {}", e),
+            ManifestError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// A texture split into a grid of equally sized frames, numbered row by row.
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteSheet {
+    pub texture: Texture,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl SpriteSheet {
+    pub fn len(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Source rect of a frame, for `DrawBuilder::src`.
+    pub fn frame(&self, index: u32) -> Rect {
+        assert!(index < self.len(), "sprite sheet has no frame {}", index);
+        let w = 1.0 / self.columns as f32;
+        let h = 1.0 / self.rows as f32;
+        Rect {
+            x: (index % self.columns) as f32 * w,
+            y: (index / self.columns) as f32 * h,
+            w,
+            h,
+        }
+    }
+}
+
+/// Everything from a manifest, handed to `Game::on_assets_loaded`.
+#[derive(Debug, Default)]
+pub struct Assets {
+    textures: HashMap<String, Texture>,
+    sprite_sheets: HashMap<String, SpriteSheet>,
+    sounds: HashMap<String, Sound>,
+    data: HashMap<String, Vec<u8>>,
+}
+
+impl Assets {
+    pub fn texture(&self, name: &str) -> Option<Texture> {
+        self.textures.get(name).copied()
+    }
+
+    pub fn sprite_sheet(&self, name: &str) -> Option<SpriteSheet> {
+        self.sprite_sheets.get(name).copied()
+    }
+
+    pub fn sound(&self, name: &str) -> Option<Sound> {
+        self.sounds.get(name).copied()
+    }
+
+    pub fn data(&self, name: &str) -> Option<&[u8]> {
+        self.data.get(name).map(Vec::as_slice)
+    }
+
+    pub fn take_data(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.data.remove(name)
+    }
+}
+
+pub(crate) enum LoadedAsset {
+    Texture(BindableTexture, Option<TextureSource>),
+    SpriteSheet(BindableTexture, Option<TextureSource>, u32, u32),
+    Sound(SoundData),
+    Data(Vec<u8>),
+}
+
+/// Assets loaded by a worker, waiting to be handed to the game.
+pub(crate) struct LoadedAssets {
+    pub(crate) assets: Vec<(String, LoadedAsset)>,
+}
+
+impl LoadedAssets {
+    pub(crate) fn register(
+        self,
+        textures: &mut Vec<BindableTexture>,
+        audio: &mut Audio,
+        hot_reload: &mut HotReload,
+    ) -> Assets {
+        let mut add_texture = |texture, source: Option<TextureSource>| {
+            textures.push(texture);
+            let texture = Texture { index: textures.len() - 1 };
+            if let Some(source) = source {
+                hot_reload.watch(texture, source);
+            }
+            texture
+        };
+        let mut assets = Assets::default();
+        for (name, asset) in self.assets {
+            match asset {
+                LoadedAsset::Texture(texture, source) => {
+                    assets.textures.insert(name, add_texture(texture, source));
+                }
+                LoadedAsset::SpriteSheet(texture, source, columns, rows) => {
+                    let texture = add_texture(texture, source);
+                    assets.sprite_sheets.insert(name, SpriteSheet { texture, columns, rows });
+                }
+                LoadedAsset::Sound(sound) => {
+                    assets.sounds.insert(name, audio.add_sound(sound));
+                }
+                LoadedAsset::Data(data) => {
+                    assets.data.insert(name, data);
+                }
+            }
+        }
+        assets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(manifest: &Manifest) -> Vec<String> {
+        manifest
+            .assets
+            .iter()
+            .map(|(name, asset)| match asset {
+                Asset::Texture(path, _) => format!("texture {} {}", name, path.display()),
+                Asset::SpriteSheet(path, columns, rows) => {
+                    format!("sprite_sheet {} {}x{} {}", name, columns, rows, path.display())
+                }
+                Asset::Sound(path) => format!("sound {} {}", name, path.display()),
+                Asset::Data(path) => format!("data {} {}", name, path.display()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let manifest = Manifest::parse(
+            "# level 3
+            [textures]
+            tiles = tiles.png
+            hero = old.png
+
+            [sprite_sheets]
+            hero = 4x2 sheets/hero walk.png
+            [sounds]
+            jump=sfx/jump.wav
+            [textures]
+            hero = hero.png
+            [data]
+            map = level3.txt
+            ",
+        )
+        .unwrap();
+        assert_eq!(describe(&manifest), [
+            "texture tiles tiles.png",
+            "sprite_sheet hero 4x2 sheets/hero walk.png",
+            "sound jump sfx/jump.wav",
+            "texture hero hero.png",
+            "data map level3.txt",
+        ]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |manifest| match Manifest::parse(manifest) {
+            Err(ManifestError::Parse { line, .. }) => line,
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        };
+        assert_eq!(err("tiles = tiles.png"), 1);
+        assert_eq!(err("[textures]\ntiles"), 2);
+        assert_eq!(err("[textures]\n\ntiles = "), 3);
+        assert_eq!(err("[sprite_sheets]\nhero = hero.png"), 2);
+        assert_eq!(err("[sprite_sheets]\nhero = 0x2 hero.png"), 2);
+        assert_eq!(err("[sprite_sheets]\nhero = 4x2"), 2);
+    }
+
+    #[test]
+    fn names_are_unique_per_kind() {
+        let manifest = Manifest::new()
+            .texture("hero", "a.png")
+            .sound("hero", "hero.wav")
+            .texture("hero", "b.png")
+            .sprite_sheet("hero", "c.png", 2, 2);
+        assert_eq!(describe(&manifest), [
+            "sound hero hero.wav",
+            "texture hero b.png",
+            "sprite_sheet hero 2x2 c.png",
+        ]);
+    }
+
+    #[test]
+    fn sprite_sheet_frames() {
+        let sheet = SpriteSheet { texture: Texture { index: 0 }, columns: 4, rows: 2 };
+        assert_eq!(sheet.len(), 8);
+        let frame = sheet.frame(5);
+        assert_eq!((frame.x, frame.y, frame.w, frame.h), (0.25, 0.5, 0.25, 0.5));
+    }
+}
@@ -0,0 +1,871 @@
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        mpsc::{channel, RecvTimeoutError, Receiver, Sender},
+    },
+    time::Duration,
+};
+use crate::{
+    Rect, Vfs,
+    decode::{self, Decoder, SoundData},
+};
+
+/// A sound loaded with `JobQueue::load_sound_from_file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sound {
+    index: usize,
+}
+
+/// A playing instance of a sound, used to change or stop it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voice(u64);
+
+/// Every voice belongs to a group, which has its own volume and can be
+/// paused as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundGroup {
+    Sfx,
+    Music,
+    Ui,
+}
+
+impl SoundGroup {
+    pub const ALL: [SoundGroup; 3] = [SoundGroup::Sfx, SoundGroup::Music, SoundGroup::Ui];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayOptions {
+    pub(crate) volume: f32,
+    pub(crate) pan: f32,
+    pub(crate) looping: bool,
+    pub(crate) group: SoundGroup,
+    pub(crate) position: Option<(f32, f32)>,
+}
+
+impl Default for PlayOptions {
+    fn default() -> PlayOptions {
+        PlayOptions {
+            volume: 1.0,
+            pan: 0.0,
+            looping: false,
+            group: SoundGroup::Sfx,
+            position: None,
+        }
+    }
+}
+
+impl PlayOptions {
+    pub fn new() -> PlayOptions {
+        PlayOptions::default()
+    }
+
+    pub fn volume(mut self, volume: f32) -> PlayOptions {
+        self.volume = volume;
+        self
+    }
+
+    /// From `-1.0` (left) to `1.0` (right).
+    pub fn pan(mut self, pan: f32) -> PlayOptions {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self
+    }
+
+    pub fn looping(mut self, looping: bool) -> PlayOptions {
+        self.looping = looping;
+        self
+    }
+
+    pub fn group(mut self, group: SoundGroup) -> PlayOptions {
+        self.group = group;
+        self
+    }
+
+    /// Plays the sound at a point in the world, attenuated and panned
+    /// relative to the last view set with `Renderer::set_view` in a frame.
+    /// See `Rolloff`.
+    pub fn position(mut self, x: f32, y: f32) -> PlayOptions {
+        self.position = Some((x, y));
+        self
+    }
+}
+
+/// How positional sounds fade with distance from the view.
+///
+/// Distances are measured in half view sizes from the center of the view,
+/// so the edge of the screen is at `1.0` no matter how far the view is
+/// zoomed.
+///
+/// The listener follows the view the renderer was left with at the end of
+/// `Game::draw`, i.e. the last `Renderer::set_view` of the frame. Views
+/// with a zero or non-finite size are ignored and the previous one is kept.
+#[derive(Debug, Clone, Copy)]
+pub struct Rolloff {
+    /// Sounds closer than this play at full volume.
+    pub start: f32,
+    /// Sounds farther away than this are silent.
+    pub end: f32,
+    /// How far sounds at the edge of the screen are panned, from `0.0` to
+    /// `1.0`.
+    pub pan: f32,
+}
+
+impl Default for Rolloff {
+    fn default() -> Rolloff {
+        Rolloff {
+            start: 1.0,
+            end: 3.0,
+            pan: 0.8,
+        }
+    }
+}
+
+impl Rolloff {
+    /// Volume and pan of a sound at `pos` when looking at `view`.
+    fn apply(&self, view: Rect, pos: (f32, f32)) -> (f32, f32) {
+        let dx = (pos.0 - (view.x + view.w * 0.5)) / (view.w * 0.5);
+        let dy = (pos.1 - (view.y + view.h * 0.5)) / (view.h * 0.5);
+        let distance = dx.abs().max(dy.abs());
+        let volume = if distance <= self.start {
+            1.0
+        } else if distance >= self.end {
+            0.0
+        } else {
+            1.0 - (distance - self.start) / (self.end - self.start)
+        };
+        (volume, dx.clamp(-1.0, 1.0) * self.pan)
+    }
+}
+
+#[derive(Debug)]
+pub enum AudioError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::Io(e) => write!(f, "failed to read sound: {}", e),
+            AudioError::Format(msg) => write!(f, "invalid sound: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+impl From<io::Error> for AudioError {
+    fn from(e: io::Error) -> AudioError {
+        AudioError::Io(e)
+    }
+}
+
+/// Where the mixed audio goes.
+///
+/// The default output is picked by `default_output`, games and tests can
+/// replace it with `Audio::set_output`.
+pub trait AudioOutput {
+    /// Frames per second the mixer should produce.
+    fn sample_rate(&self) -> u32;
+    /// Starts pulling audio with `MixerHandle::mix`, usually from an audio
+    /// callback on another thread.
+    fn start(&mut self, mixer: MixerHandle);
+}
+
+/// Output that renders the mix on demand instead of playing it, for tests.
+/// Clones share the same mixer.
+#[derive(Clone)]
+pub struct BufferOutput {
+    sample_rate: u32,
+    mixer: Arc<Mutex<Option<MixerHandle>>>,
+}
+
+impl BufferOutput {
+    pub fn new(sample_rate: u32) -> BufferOutput {
+        BufferOutput {
+            sample_rate,
+            mixer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Mixes the next `frames` frames, as interleaved stereo samples.
+    pub fn render(&self, frames: usize) -> Vec<f32> {
+        let mut out = vec![0.0; frames * 2];
+        if let Some(mixer) = &*self.mixer.lock().unwrap() {
+            mixer.mix(&mut out);
+        }
+        out
+    }
+}
+
+impl AudioOutput for BufferOutput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, mixer: MixerHandle) {
+        *self.mixer.lock().unwrap() = Some(mixer);
+    }
+}
+
+/// Plays through the default audio device.
+#[cfg(feature = "cpal")]
+pub struct CpalOutput {
+    device: cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    stream: Option<cpal::Stream>,
+}
+
+#[cfg(feature = "cpal")]
+impl CpalOutput {
+    pub fn new() -> Result<CpalOutput, Box<dyn std::error::Error>> {
+        use cpal::traits::{DeviceTrait, HostTrait};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let config = device.default_output_config()?;
+        Ok(CpalOutput { device, config, stream: None })
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl AudioOutput for CpalOutput {
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate().0
+    }
+
+    fn start(&mut self, mixer: MixerHandle) {
+        use cpal::{traits::{DeviceTrait, StreamTrait}, OutputCallbackInfo, SampleFormat};
+
+        fn fill<T>(mixer: &MixerHandle, stereo: &mut Vec<f32>, channels: usize, data: &mut [T], convert: fn(f32) -> T) {
+            stereo.resize(data.len() / channels * 2, 0.0);
+            mixer.mix(stereo);
+            for (frame, lr) in data.chunks_mut(channels).zip(stereo.chunks(2)) {
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    *sample = convert(match (channels, channel) {
+                        (1, _) => (lr[0] + lr[1]) * 0.5,
+                        (_, 0) | (_, 1) => lr[channel],
+                        _ => 0.0,
+                    });
+                }
+            }
+        }
+
+        let channels = usize::from(self.config.channels());
+        let config = self.config.config();
+        let on_error = |e: cpal::StreamError| eprintln!("audio output failed: {}", e);
+        let mut stereo = Vec::new();
+        let stream = match self.config.sample_format() {
+            SampleFormat::F32 => self.device.build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &OutputCallbackInfo| {
+                    fill(&mixer, &mut stereo, channels, data, |s| s)
+                },
+                on_error,
+            ),
+            SampleFormat::I16 => self.device.build_output_stream(
+                &config,
+                move |data: &mut [i16], _: &OutputCallbackInfo| {
+                    fill(&mixer, &mut stereo, channels, data, |s| (s * 32767.0) as i16)
+                },
+                on_error,
+            ),
+            SampleFormat::U16 => self.device.build_output_stream(
+                &config,
+                move |data: &mut [u16], _: &OutputCallbackInfo| {
+                    fill(&mixer, &mut stereo, channels, data, |s| ((s + 1.0) * 32767.5) as u16)
+                },
+                on_error,
+            ),
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => return eprintln!("failed to open audio output: {}", e),
+        };
+        if let Err(e) = stream.play() {
+            eprintln!("failed to start audio output: {}", e);
+        }
+        self.stream = Some(stream);
+    }
+}
+
+pub(crate) fn default_output() -> Option<Box<dyn AudioOutput>> {
+    #[cfg(feature = "cpal")]
+    {
+        match CpalOutput::new() {
+            Ok(output) => return Some(Box::new(output)),
+            Err(e) => eprintln!("failed to open audio output: {}", e),
+        }
+    }
+    None
+}
+
+/// How often the stream thread refills streams, well below the second of
+/// audio they keep buffered.
+const STREAM_REFILL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decoded samples of a streamed sound that were not played yet.
+#[derive(Default)]
+struct StreamBuffer {
+    samples: VecDeque<f32>,
+    finished: bool,
+}
+
+enum Source {
+    Sound(Arc<SoundData>),
+    Stream(Arc<Mutex<StreamBuffer>>),
+}
+
+struct PlayingVoice {
+    id: u64,
+    source: Source,
+    channels: usize,
+    /// Source frames to advance per output frame.
+    step: f64,
+    /// Position in source frames. For streams relative to the front of the
+    /// buffer.
+    position: f64,
+    options: PlayOptions,
+}
+
+fn frame(samples: &[f32], channels: usize, index: usize) -> (f32, f32) {
+    let i = index * channels;
+    if channels == 1 {
+        (samples[i], samples[i])
+    } else {
+        (samples[i], samples[i + 1])
+    }
+}
+
+impl PlayingVoice {
+    /// Adds the voice to `out`, returns false once it finished.
+    fn mix(&mut self, out: &mut [f32], gain: (f32, f32)) -> bool {
+        match &self.source {
+            Source::Sound(sound) => {
+                let samples = &sound.samples[..];
+                let len = samples.len() / self.channels;
+                if len == 0 {
+                    return false;
+                }
+                for out in out.chunks_mut(2) {
+                    if self.position >= len as f64 {
+                        if !self.options.looping {
+                            return false;
+                        }
+                        self.position %= len as f64;
+                    }
+                    let index = self.position as usize;
+                    let t = (self.position - index as f64) as f32;
+                    let next = match index + 1 {
+                        next if next < len => next,
+                        _ if self.options.looping => 0,
+                        _ => index,
+                    };
+                    let (l0, r0) = frame(samples, self.channels, index);
+                    let (l1, r1) = frame(samples, self.channels, next);
+                    out[0] += (l0 + (l1 - l0) * t) * gain.0;
+                    out[1] += (r0 + (r1 - r0) * t) * gain.1;
+                    self.position += self.step;
+                }
+                true
+            }
+            Source::Stream(buffer) => {
+                let mut buffer = buffer.lock().unwrap();
+                let samples = buffer.samples.make_contiguous();
+                let len = samples.len() / self.channels;
+                for out in out.chunks_mut(2) {
+                    let index = self.position as usize;
+                    if index >= len {
+                        // Ran dry, either for good or until the next refill.
+                        break;
+                    }
+                    let t = (self.position - index as f64) as f32;
+                    let next = (index + 1).min(len - 1);
+                    let (l0, r0) = frame(samples, self.channels, index);
+                    let (l1, r1) = frame(samples, self.channels, next);
+                    out[0] += (l0 + (l1 - l0) * t) * gain.0;
+                    out[1] += (r0 + (r1 - r0) * t) * gain.1;
+                    self.position += self.step;
+                }
+                let played = (self.position as usize).min(len);
+                buffer.samples.drain(..played * self.channels);
+                self.position -= played as f64;
+                !(buffer.finished && buffer.samples.len() < self.channels)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GroupState {
+    volume: f32,
+    paused: bool,
+}
+
+pub(crate) struct Mixer {
+    sample_rate: u32,
+    voices: Vec<PlayingVoice>,
+    groups: [GroupState; 3],
+    master_volume: f32,
+    listener: Rect,
+    rolloff: Rolloff,
+}
+
+impl Mixer {
+    fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sample_rate,
+            voices: Vec::new(),
+            groups: [GroupState { volume: 1.0, paused: false }; 3],
+            master_volume: 1.0,
+            listener: Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 },
+            rolloff: Rolloff::default(),
+        }
+    }
+
+    fn mix(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+        }
+        let mut i = 0;
+        while i < self.voices.len() {
+            let voice = &mut self.voices[i];
+            let group = self.groups[voice.options.group.index()];
+            let (volume, pan) = match voice.options.position {
+                Some(pos) => self.rolloff.apply(self.listener, pos),
+                None => (1.0, 0.0),
+            };
+            let volume = voice.options.volume * volume * group.volume * self.master_volume;
+            let pan = (voice.options.pan + pan).clamp(-1.0, 1.0);
+            let gain = ((1.0 - pan).min(1.0) * volume, (1.0 + pan).min(1.0) * volume);
+            if group.paused || voice.mix(out, gain) {
+                i += 1;
+            } else {
+                self.voices.swap_remove(i);
+            }
+        }
+        for sample in out.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+
+    fn voice(&mut self, voice: Voice) -> Option<&mut PlayingVoice> {
+        self.voices.iter_mut().find(|v| v.id == voice.0)
+    }
+}
+
+/// Shared access to the mixer for `AudioOutput` implementations.
+#[derive(Clone)]
+pub struct MixerHandle {
+    mixer: Arc<Mutex<Mixer>>,
+}
+
+impl MixerHandle {
+    pub fn sample_rate(&self) -> u32 {
+        self.mixer.lock().unwrap().sample_rate
+    }
+
+    /// Mixes the next `out.len() / 2` frames into `out`, as interleaved
+    /// stereo samples.
+    pub fn mix(&self, out: &mut [f32]) {
+        self.mixer.lock().unwrap().mix(out);
+    }
+}
+
+/// A streamed voice, decoded on the stream thread ahead of playback.
+struct Stream {
+    decoder: Box<dyn Decoder>,
+    buffer: Arc<Mutex<StreamBuffer>>,
+    looping: bool,
+}
+
+impl Stream {
+    /// Keeps about a second of audio buffered.
+    fn fill(&mut self) {
+        let channels = usize::from(self.decoder.channels());
+        let target = self.decoder.sample_rate() as usize * channels;
+        let mut samples = Vec::new();
+        loop {
+            let buffered = self.buffer.lock().unwrap().samples.len();
+            if buffered >= target {
+                return;
+            }
+            samples.clear();
+            let read = self.decoder.read(4096, &mut samples).and_then(|frames| {
+                if frames == 0 && self.looping {
+                    self.decoder.rewind()?;
+                    self.decoder.read(4096, &mut samples)
+                } else {
+                    Ok(frames)
+                }
+            });
+            let mut buffer = self.buffer.lock().unwrap();
+            match read {
+                Ok(0) => {}
+                Ok(_) => {
+                    buffer.samples.extend(&samples);
+                    continue;
+                }
+                Err(e) => eprintln!("failed to stream sound: {}", e),
+            }
+            buffer.finished = true;
+            return;
+        }
+    }
+
+    /// Whether the voice is gone from the mixer, it holds the only other
+    /// reference to the buffer.
+    fn is_stopped(&self) -> bool {
+        Arc::strong_count(&self.buffer) == 1
+    }
+}
+
+/// Keeps streams fed until the `Audio` that sends them is dropped.
+fn run_streams(new_streams: Receiver<Stream>) {
+    let mut streams = Vec::<Stream>::new();
+    loop {
+        match new_streams.recv_timeout(STREAM_REFILL_INTERVAL) {
+            Ok(stream) => streams.push(stream),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        streams.retain(|s| !s.is_stopped());
+        for stream in &mut streams {
+            stream.fill();
+        }
+    }
+}
+
+/// Plays sounds, see `Ctx::audio`.
+pub struct Audio {
+    output: Option<Box<dyn AudioOutput>>,
+    mixer: Arc<Mutex<Mixer>>,
+    sounds: Vec<Arc<SoundData>>,
+    /// Started on the first `play_stream`.
+    streams: Option<Sender<Stream>>,
+    next_voice: u64,
+    vfs: Vfs,
+}
+
+impl Audio {
+    pub(crate) fn new(output: Option<Box<dyn AudioOutput>>, vfs: Vfs) -> Audio {
+        let mut audio = Audio {
+            vfs,
+            output: None,
+            mixer: Arc::new(Mutex::new(Mixer::new(48000))),
+            sounds: Vec::new(),
+            streams: None,
+            next_voice: 0,
+        };
+        if let Some(output) = output {
+            audio.set_output(output);
+        }
+        audio
+    }
+
+    /// Switches to a different output. Stops all voices.
+    pub fn set_output(&mut self, mut output: Box<dyn AudioOutput>) {
+        let old = {
+            let mut mixer = self.mixer.lock().unwrap();
+            mixer.voices.clear();
+            Mixer { voices: Vec::new(), ..*mixer }
+        };
+        let mixer = Mixer {
+            sample_rate: output.sample_rate(),
+            ..old
+        };
+        self.mixer = Arc::new(Mutex::new(mixer));
+        output.start(MixerHandle { mixer: self.mixer.clone() });
+        self.output = Some(output);
+    }
+
+    pub fn play(&mut self, sound: Sound) -> Voice {
+        self.play_with(sound, PlayOptions::default())
+    }
+
+    pub fn play_with(&mut self, sound: Sound, options: PlayOptions) -> Voice {
+        let sound = self.sounds[sound.index].clone();
+        let channels = usize::from(sound.channels);
+        let sample_rate = sound.sample_rate;
+        self.start(Source::Sound(sound), channels, sample_rate, options)
+    }
+
+    /// Plays a sound while decoding it bit by bit on a background thread,
+    /// instead of loading all of it up front. Meant for music and other long
+    /// sounds.
+    pub fn play_stream(&mut self, path: &Path, options: PlayOptions) -> Result<Voice, AudioError> {
+        let decoder = decode::open(&self.vfs, path)?;
+        let channels = usize::from(decoder.channels());
+        let sample_rate = decoder.sample_rate();
+        let buffer = Arc::new(Mutex::new(StreamBuffer::default()));
+        let stream = Stream {
+            decoder,
+            buffer: buffer.clone(),
+            looping: options.looping,
+        };
+        let voice = self.start(Source::Stream(buffer), channels, sample_rate, options);
+        if self.output.is_some() {
+            let streams = self.streams.get_or_insert_with(|| {
+                let (sender, receiver) = channel();
+                std::thread::spawn(move || run_streams(receiver));
+                sender
+            });
+            streams.send(stream).expect("stream thread died");
+        }
+        Ok(voice)
+    }
+
+    fn start(&mut self, source: Source, channels: usize, sample_rate: u32, options: PlayOptions) -> Voice {
+        let voice = Voice(self.next_voice);
+        self.next_voice += 1;
+        // Without an output nothing would ever finish playing.
+        if self.output.is_none() {
+            return voice;
+        }
+        let mut mixer = self.mixer.lock().unwrap();
+        let step = f64::from(sample_rate) / f64::from(mixer.sample_rate);
+        mixer.voices.push(PlayingVoice {
+            id: voice.0,
+            source,
+            channels,
+            step,
+            position: 0.0,
+            options,
+        });
+        voice
+    }
+
+    pub fn stop(&mut self, voice: Voice) {
+        self.mixer.lock().unwrap().voices.retain(|v| v.id != voice.0);
+    }
+
+    pub fn stop_group(&mut self, group: SoundGroup) {
+        self.mixer.lock().unwrap().voices.retain(|v| v.options.group != group);
+    }
+
+    pub fn is_playing(&self, voice: Voice) -> bool {
+        self.mixer.lock().unwrap().voices.iter().any(|v| v.id == voice.0)
+    }
+
+    pub fn set_volume(&mut self, voice: Voice, volume: f32) {
+        if let Some(voice) = self.mixer.lock().unwrap().voice(voice) {
+            voice.options.volume = volume;
+        }
+    }
+
+    pub fn set_pan(&mut self, voice: Voice, pan: f32) {
+        if let Some(voice) = self.mixer.lock().unwrap().voice(voice) {
+            voice.options.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Moves a voice started with `PlayOptions::position`.
+    pub fn set_position(&mut self, voice: Voice, x: f32, y: f32) {
+        if let Some(voice) = self.mixer.lock().unwrap().voice(voice) {
+            voice.options.position = Some((x, y));
+        }
+    }
+
+    pub fn rolloff(&self) -> Rolloff {
+        self.mixer.lock().unwrap().rolloff
+    }
+
+    pub fn set_rolloff(&mut self, rolloff: Rolloff) {
+        assert!(rolloff.start < rolloff.end, "rolloff must start before it ends");
+        self.mixer.lock().unwrap().rolloff = rolloff;
+    }
+
+    pub fn group_volume(&self, group: SoundGroup) -> f32 {
+        self.mixer.lock().unwrap().groups[group.index()].volume
+    }
+
+    pub fn set_group_volume(&mut self, group: SoundGroup, volume: f32) {
+        self.mixer.lock().unwrap().groups[group.index()].volume = volume;
+    }
+
+    pub fn is_group_paused(&self, group: SoundGroup) -> bool {
+        self.mixer.lock().unwrap().groups[group.index()].paused
+    }
+
+    pub fn set_group_paused(&mut self, group: SoundGroup, paused: bool) {
+        self.mixer.lock().unwrap().groups[group.index()].paused = paused;
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.mixer.lock().unwrap().master_volume
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.mixer.lock().unwrap().master_volume = volume;
+    }
+
+    pub(crate) fn add_sound(&mut self, sound: SoundData) -> Sound {
+        self.sounds.push(Arc::new(sound));
+        Sound { index: self.sounds.len() - 1 }
+    }
+
+    pub(crate) fn set_listener(&mut self, view: Rect) {
+        // an empty view would divide by zero in `Rolloff::apply`
+        let usable = |size: f32| size != 0.0 && size.is_finite();
+        if usable(view.w) && usable(view.h) && view.x.is_finite() && view.y.is_finite() {
+            self.mixer.lock().unwrap().listener = view;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::MemoryMount;
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn setup() -> (Audio, BufferOutput, Sound) {
+        let output = BufferOutput::new(RATE);
+        let mut audio = Audio::new(Some(Box::new(output.clone())), Vfs::new());
+        let sound = audio.add_sound(SoundData {
+            samples: vec![0.5; RATE as usize],
+            channels: 1,
+            sample_rate: RATE,
+        });
+        (audio, output, sound)
+    }
+
+    fn assert_frame(out: &[f32], expected: (f32, f32)) {
+        for frame in out.chunks(2) {
+            assert!(
+                (frame[0] - expected.0).abs() < 1e-6 && (frame[1] - expected.1).abs() < 1e-6,
+                "expected {:?}, got {:?}",
+                expected,
+                frame,
+            );
+        }
+    }
+
+    #[test]
+    fn volume_and_pan() {
+        let (mut audio, output, sound) = setup();
+        let voice = audio.play_with(sound, PlayOptions::new().volume(0.5));
+        assert_frame(&output.render(10), (0.25, 0.25));
+
+        audio.set_pan(voice, 1.0);
+        assert_frame(&output.render(10), (0.0, 0.25));
+        audio.set_pan(voice, -0.5);
+        assert_frame(&output.render(10), (0.25, 0.125));
+
+        audio.set_master_volume(0.5);
+        audio.set_pan(voice, 0.0);
+        assert_frame(&output.render(10), (0.125, 0.125));
+
+        // voices add up and the mix is clipped
+        audio.set_master_volume(4.0);
+        audio.play(sound);
+        assert_frame(&output.render(10), (1.0, 1.0));
+    }
+
+    #[test]
+    fn groups() {
+        let (mut audio, output, sound) = setup();
+        let sfx = audio.play(sound);
+        let music = audio.play_with(sound, PlayOptions::new().group(SoundGroup::Music));
+        assert_frame(&output.render(10), (1.0, 1.0));
+
+        audio.set_group_volume(SoundGroup::Music, 0.0);
+        assert_frame(&output.render(10), (0.5, 0.5));
+        audio.set_group_volume(SoundGroup::Music, 0.5);
+        assert_frame(&output.render(10), (0.75, 0.75));
+
+        // paused groups keep their place
+        audio.set_group_paused(SoundGroup::Sfx, true);
+        assert_frame(&output.render(RATE as usize - 30), (0.25, 0.25));
+        audio.set_group_paused(SoundGroup::Sfx, false);
+        assert_frame(&output.render(10), (0.5, 0.5));
+        assert!(!audio.is_playing(music));
+        assert!(audio.is_playing(sfx));
+
+        audio.stop_group(SoundGroup::Sfx);
+        assert!(!audio.is_playing(sfx));
+        assert_frame(&output.render(10), (0.0, 0.0));
+    }
+
+    #[test]
+    fn sounds_finish_unless_looping() {
+        let (mut audio, output, sound) = setup();
+        let once = audio.play(sound);
+        let looping = audio.play_with(sound, PlayOptions::new().looping(true).volume(0.0));
+        output.render(RATE as usize + 1);
+        assert!(!audio.is_playing(once));
+        assert!(audio.is_playing(looping));
+    }
+
+    #[test]
+    fn positional_sounds() {
+        let (mut audio, output, sound) = setup();
+        audio.set_listener(Rect { x: 0.0, y: 0.0, w: 200.0, h: 100.0 });
+        let voice = audio.play_with(sound, PlayOptions::new().position(100.0, 50.0));
+        assert_frame(&output.render(10), (0.5, 0.5));
+        // right edge of the view, at full volume but panned
+        audio.set_position(voice, 200.0, 50.0);
+        assert_frame(&output.render(10), (0.1, 0.5));
+        // past the end of the rolloff
+        audio.set_position(voice, 100.0, 250.0);
+        assert_frame(&output.render(10), (0.0, 0.0));
+
+        // e.g. a minimized window, keeps the previous view
+        audio.set_listener(Rect { x: 0.0, y: 0.0, w: 0.0, h: 0.0 });
+        audio.set_listener(Rect { x: f32::NAN, y: 0.0, w: 1.0, h: 1.0 });
+        audio.set_position(voice, 200.0, 50.0);
+        assert_frame(&output.render(10), (0.1, 0.5));
+    }
+
+    fn wav(samples: &[i16]) -> Vec<u8> {
+        let data_len = samples.len() as u32 * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&RATE.to_le_bytes());
+        wav.extend_from_slice(&(RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn streams_are_decoded_in_the_background() {
+        let (mut audio, output, _) = setup();
+        audio.vfs.mount(MemoryMount::new().with("music.wav", wav(&vec![16384; 3 * RATE as usize])));
+        let voice = audio.play_stream(Path::new("music.wav"), PlayOptions::new()).unwrap();
+
+        let start = Instant::now();
+        while output.render(1)[0] == 0.0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "stream never started");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // more than the first second that is buffered up front
+        let mut played = 1;
+        while audio.is_playing(voice) {
+            assert!(start.elapsed() < Duration::from_secs(10), "stream never finished");
+            let out = output.render(RATE as usize / 10);
+            played += out.chunks(2).filter(|frame| frame[0] != 0.0).count();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(played, 3 * RATE as usize);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    time::Duration,
+};
+
+/// How often the game checks for the text while a request is pending.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Text clipboard used by `Ctx::clipboard_request_text` and
+/// `Ctx::clipboard_set_text`.
+///
+/// The default is picked by `default_clipboard`, games and tests can
+/// replace it with `Ctx::set_clipboard`.
+pub trait Clipboard {
+    /// Starts reading the text. Must not block, the system clipboard can
+    /// take a while to answer.
+    fn request_text(&mut self);
+    /// The text of the oldest unanswered request, `None` while it isn't
+    /// there yet.
+    fn poll_text(&mut self) -> Option<io::Result<String>>;
+    fn set_text(&mut self, text: &str) -> io::Result<()>;
+}
+
+/// Clipboard that only lives inside the game.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryClipboard {
+    text: String,
+    requests: VecDeque<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> MemoryClipboard {
+        MemoryClipboard::default()
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn request_text(&mut self) {
+        self.requests.push_back(self.text.clone());
+    }
+
+    fn poll_text(&mut self) -> Option<io::Result<String>> {
+        self.requests.pop_front().map(Ok)
+    }
+
+    fn set_text(&mut self, text: &str) -> io::Result<()> {
+        self.text = text.to_string();
+        Ok(())
+    }
+}
+
+/// A command line tool pair that copies text from stdin and pastes it to
+/// stdout.
+struct Tool {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+const WAYLAND_TOOLS: &[Tool] = &[
+    Tool { copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"] },
+];
+
+const X11_TOOLS: &[Tool] = &[
+    Tool { copy: &["xclip", "-selection", "clipboard"], paste: &["xclip", "-selection", "clipboard", "-o"] },
+    Tool { copy: &["xsel", "--clipboard", "--input"], paste: &["xsel", "--clipboard", "--output"] },
+];
+
+const MACOS_TOOLS: &[Tool] = &[
+    Tool { copy: &["pbcopy"], paste: &["pbpaste"] },
+];
+
+enum Request {
+    Copy(String),
+    Paste,
+}
+
+/// The desktop clipboard, accessed through `wl-copy`/`wl-paste` on Wayland,
+/// `xclip` or `xsel` on X11 and `pbcopy`/`pbpaste` on macOS. One of these
+/// tools has to be installed, Windows is not supported.
+///
+/// The tools run on a background thread so a slow or hung tool can't stall
+/// the game. `set_text` returns right away and reports failures on stderr.
+pub struct SystemClipboard {
+    requests: Sender<Request>,
+    texts: Receiver<io::Result<String>>,
+    /// Paste requests that weren't answered yet.
+    pending: usize,
+}
+
+impl SystemClipboard {
+    /// Returns `None` if there is no supported clipboard on this system.
+    pub fn new() -> Option<SystemClipboard> {
+        let tools = if cfg!(target_os = "macos") {
+            MACOS_TOOLS
+        } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            WAYLAND_TOOLS
+        } else if std::env::var_os("DISPLAY").is_some() {
+            X11_TOOLS
+        } else {
+            return None;
+        };
+        let (requests, receiver) = channel();
+        let (text_sender, texts) = channel();
+        std::thread::spawn(move || {
+            for request in receiver {
+                match request {
+                    Request::Copy(text) => {
+                        if let Err(e) = run_tools(tools, |tool| tool.copy, Some(&text)) {
+                            eprintln!("failed to copy to the clipboard: {}", e);
+                        }
+                    }
+                    Request::Paste => {
+                        text_sender.send(run_tools(tools, |tool| tool.paste, None)).ok();
+                    }
+                }
+            }
+        });
+        Some(SystemClipboard { requests, texts, pending: 0 })
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "clipboard thread stopped")
+}
+
+/// Runs the first of the tools that is installed.
+fn run_tools(
+    tools: &[Tool],
+    args: impl Fn(&Tool) -> &[&str],
+    input: Option<&str>,
+) -> io::Result<String> {
+    let mut result = Err(io::Error::new(io::ErrorKind::NotFound, "no clipboard tool found"));
+    for tool in tools {
+        result = run_tool(args(tool), input);
+        match &result {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            _ => break,
+        }
+    }
+    result
+}
+
+fn run_tool(args: &[&str], input: Option<&str>) -> io::Result<String> {
+    let mut child = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(if input.is_some() { Stdio::null() } else { Stdio::piped() })
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(input) = input {
+        // Dropping stdin closes it, which lets the tool finish.
+        child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} failed with {}", args[0], output.status),
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "clipboard is not UTF-8"))
+}
+
+impl Clipboard for SystemClipboard {
+    fn request_text(&mut self) {
+        // requests run in order, so this also sees text set just before
+        self.requests.send(Request::Paste).ok();
+        self.pending += 1;
+    }
+
+    fn poll_text(&mut self) -> Option<io::Result<String>> {
+        if self.pending == 0 {
+            return None;
+        }
+        let text = match self.texts.try_recv() {
+            Ok(text) => text,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(closed()),
+        };
+        self.pending -= 1;
+        Some(text)
+    }
+
+    fn set_text(&mut self, text: &str) -> io::Result<()> {
+        self.requests.send(Request::Copy(text.to_owned())).map_err(|_| closed())
+    }
+}
+
+pub(crate) fn default_clipboard() -> Box<dyn Clipboard> {
+    match SystemClipboard::new() {
+        Some(clipboard) => Box::new(clipboard),
+        None => {
+            eprintln!("no system clipboard found, copy and paste only work within the game");
+            Box::new(MemoryClipboard::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_clipboard() {
+        let mut clipboard = MemoryClipboard::new();
+        assert!(clipboard.poll_text().is_none());
+        clipboard.request_text();
+        clipboard.set_text("hello").unwrap();
+        clipboard.request_text();
+        assert_eq!(clipboard.poll_text().unwrap().unwrap(), "");
+        assert_eq!(clipboard.poll_text().unwrap().unwrap(), "hello");
+        assert!(clipboard.poll_text().is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+use crate::{
+    queue::DEFAULT_JOB_WORKERS,
+    timer::{DEFAULT_MAX_BACKLOG, DEFAULT_TICK_RATE, MAX_TICK_RATE},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PresentMode {
+    /// Waits for vertical blank, never tears. Supported everywhere.
+    Vsync,
+    /// Presents the newest frame on vertical blank, rendering does not
+    /// block. Falls back to `Vsync` where unsupported.
+    Mailbox,
+    /// Presents immediately, may tear.
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> wgpu::PresentMode {
+        match mode {
+            PresentMode::Vsync => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+/// Graphics API to draw with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl From<Backend> for wgpu::BackendBit {
+    fn from(backend: Backend) -> wgpu::BackendBit {
+        match backend {
+            Backend::Vulkan => wgpu::BackendBit::VULKAN,
+            Backend::Metal => wgpu::BackendBit::METAL,
+            Backend::Dx12 => wgpu::BackendBit::DX12,
+            Backend::Dx11 => wgpu::BackendBit::DX11,
+            Backend::Gl => wgpu::BackendBit::GL,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PowerPreference {
+    /// Prefers integrated GPUs.
+    LowPower,
+    /// Prefers discrete GPUs.
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> wgpu::PowerPreference {
+        match preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fullscreen {
+    /// A borderless window covering the whole monitor.
+    Borderless,
+    /// Takes over the monitor using its largest video mode.
+    Exclusive,
+}
+
+/// Startup options for `run_with_config` and `try_run`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) record_input: Option<PathBuf>,
+    pub(crate) replay_input: Option<PathBuf>,
+    pub(crate) tick_rate: f32,
+    pub(crate) max_backlog: f32,
+    pub(crate) present_mode: PresentMode,
+    pub(crate) backend: Option<Backend>,
+    pub(crate) power_preference: PowerPreference,
+    pub(crate) fallback_adapter: bool,
+    pub(crate) frame_rate_cap: Option<f32>,
+    pub(crate) title: String,
+    pub(crate) size: Option<(u32, u32)>,
+    pub(crate) min_size: Option<(u32, u32)>,
+    pub(crate) max_size: Option<(u32, u32)>,
+    pub(crate) resizable: bool,
+    pub(crate) icon: Option<PathBuf>,
+    pub(crate) fullscreen: Option<Fullscreen>,
+    pub(crate) job_workers: usize,
+    pub(crate) hot_reload: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            record_input: None,
+            replay_input: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            max_backlog: DEFAULT_MAX_BACKLOG,
+            present_mode: PresentMode::Vsync,
+            backend: None,
+            power_preference: PowerPreference::HighPerformance,
+            fallback_adapter: true,
+            frame_rate_cap: None,
+            title: "barf".to_string(),
+            size: None,
+            min_size: None,
+            max_size: None,
+            resizable: true,
+            icon: None,
+            fullscreen: None,
+            job_workers: DEFAULT_JOB_WORKERS,
+            hot_reload: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    /// Number of fixed updates per second, 60 by default and at most 10000.
+    pub fn tick_rate(mut self, tick_rate: f32) -> Config {
+        assert!(
+            tick_rate > 0.0 && tick_rate <= MAX_TICK_RATE,
+            "tick rate must be positive and at most {}",
+            MAX_TICK_RATE,
+        );
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    /// Longest stretch of time, in seconds, that updates will try to catch
+    /// up on after a hitch. Anything beyond that is dropped.
+    pub fn max_backlog(mut self, seconds: f32) -> Config {
+        assert!(seconds > 0.0 && seconds.is_finite(), "max backlog must be positive and finite");
+        self.max_backlog = seconds;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Config {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Only uses the given graphics API. By default Vulkan, Metal or DirectX
+    /// 12 is picked, whichever is available.
+    pub fn backend(mut self, backend: Backend) -> Config {
+        self.backend = Some(backend);
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: PowerPreference) -> Config {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Whether to try other GPUs if none matches `power_preference`, and
+    /// every graphics API if none was chosen with `backend`. An explicitly
+    /// chosen `backend` is never replaced. Enabled by default.
+    pub fn fallback_adapter(mut self, fallback: bool) -> Config {
+        self.fallback_adapter = fallback;
+        self
+    }
+
+    /// Limits how many frames per second are drawn, see
+    /// `Ctx::set_frame_rate_cap`.
+    pub fn frame_rate_cap(mut self, fps: f32) -> Config {
+        assert!(fps > 0.0, "frame rate cap must be positive");
+        self.frame_rate_cap = Some(fps);
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Config {
+        self.title = title.into();
+        self
+    }
+
+    /// Initial size of the window in physical pixels, like `Ctx::screen_size`.
+    pub fn size(mut self, width: u32, height: u32) -> Config {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn min_size(mut self, width: u32, height: u32) -> Config {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    pub fn max_size(mut self, width: u32, height: u32) -> Config {
+        self.max_size = Some((width, height));
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Config {
+        self.resizable = resizable;
+        self
+    }
+
+    /// Image file to use as the window icon.
+    pub fn icon(mut self, path: impl Into<PathBuf>) -> Config {
+        self.icon = Some(path.into());
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: Fullscreen) -> Config {
+        self.fullscreen = Some(fullscreen);
+        self
+    }
+
+    /// Number of threads running jobs from the `JobQueue`, 2 by default.
+    pub fn job_workers(mut self, workers: usize) -> Config {
+        assert!(workers > 0, "need at least one job worker");
+        self.job_workers = workers;
+        self
+    }
+
+    /// Reloads textures loaded from files whenever the files change, for
+    /// development. Handles keep working and show the new image.
+    pub fn hot_reload(mut self, hot_reload: bool) -> Config {
+        self.hot_reload = hot_reload;
+        self
+    }
+
+    /// Records the input of every update into the given file.
+    pub fn record_input(mut self, path: impl Into<PathBuf>) -> Config {
+        self.record_input = Some(path.into());
+        self
+    }
+
+    /// Plays back a recording made with `record_input` instead of live
+    /// input. Live input resumes once the recording runs out.
+    pub fn replay_input(mut self, path: impl Into<PathBuf>) -> Config {
+        self.replay_input = Some(path.into());
+        self
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+use crate::{AudioError, JobProgress, Vfs};
+
+/// A fully decoded sound, interleaved samples in `-1.0..=1.0`.
+pub(crate) struct SoundData {
+    pub(crate) samples: Vec<f32>,
+    pub(crate) channels: u16,
+    pub(crate) sample_rate: u32,
+}
+
+/// Reads interleaved samples from an audio file bit by bit, for streaming.
+pub(crate) trait Decoder: Send {
+    fn channels(&self) -> u16;
+    fn sample_rate(&self) -> u32;
+    /// Length in frames, if known up front.
+    fn frames(&self) -> Option<u64>;
+    /// Appends up to `frames` frames to `out` and returns how many were
+    /// read, 0 once the end is reached.
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, AudioError>;
+    fn rewind(&mut self) -> Result<(), AudioError>;
+}
+
+/// Picks a decoder based on the file extension.
+pub(crate) fn open(vfs: &Vfs, path: &Path) -> Result<Box<dyn Decoder>, AudioError> {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("wav") => Ok(Box::new(WavDecoder::new(vfs.open(path)?)?)),
+        #[cfg(feature = "lewton")]
+        Some("ogg") => Ok(Box::new(OggDecoder::open(vfs, path)?)),
+        #[cfg(feature = "claxon")]
+        Some("flac") => Ok(Box::new(FlacDecoder::open(vfs, path)?)),
+        _ => Err(AudioError::Format(format!("unsupported audio file {}", path.display()))),
+    }
+}
+
+/// Returns `None` if the job was cancelled part way.
+pub(crate) fn decode_file(
+    vfs: &Vfs,
+    path: &Path,
+    progress: &JobProgress<'_>,
+) -> Result<Option<SoundData>, AudioError> {
+    let mut decoder = open(vfs, path)?;
+    let mut samples = Vec::new();
+    let mut frames = 0;
+    loop {
+        let read = decoder.read(4096, &mut samples)?;
+        if read == 0 {
+            break;
+        }
+        if progress.is_cancelled() {
+            return Ok(None);
+        }
+        frames += read as u64;
+        if let Some(total) = decoder.frames() {
+            progress.set(frames as f32 / total as f32);
+        }
+    }
+    if decoder.frames().is_none() {
+        progress.set(1.0);
+    }
+    Ok(Some(SoundData {
+        samples,
+        channels: decoder.channels(),
+        sample_rate: decoder.sample_rate(),
+    }))
+}
+
+#[derive(Clone, Copy)]
+enum WavFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl WavFormat {
+    fn bytes(self) -> usize {
+        match self {
+            WavFormat::U8 => 1,
+            WavFormat::I16 => 2,
+            WavFormat::I24 => 3,
+            WavFormat::I32 | WavFormat::F32 => 4,
+            WavFormat::F64 => 8,
+        }
+    }
+
+    fn sample(self, b: &[u8]) -> f32 {
+        match self {
+            WavFormat::U8 => (f32::from(b[0]) - 128.0) / 128.0,
+            WavFormat::I16 => f32::from(i16::from_le_bytes([b[0], b[1]])) / 32768.0,
+            WavFormat::I24 => (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+            WavFormat::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+            WavFormat::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            WavFormat::F64 => {
+                f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+            }
+        }
+    }
+}
+
+struct WavDecoder<R> {
+    reader: R,
+    format: WavFormat,
+    channels: u16,
+    sample_rate: u32,
+    data_start: u64,
+    data_len: u64,
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn bad_wav(message: &str) -> AudioError {
+    AudioError::Format(format!("invalid WAV file: {}", message))
+}
+
+impl<R: Read + Seek> WavDecoder<R> {
+    fn new(mut reader: R) -> Result<WavDecoder<R>, AudioError> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(bad_wav("not a RIFF WAVE file"));
+        }
+        let mut format = None;
+        loop {
+            let mut id = [0; 4];
+            reader.read_exact(&mut id)?;
+            let len = read_u32(&mut reader)?;
+            match &id {
+                b"fmt " => {
+                    let mut tag = read_u16(&mut reader)?;
+                    let channels = read_u16(&mut reader)?;
+                    let sample_rate = read_u32(&mut reader)?;
+                    let _byte_rate = read_u32(&mut reader)?;
+                    let _block_align = read_u16(&mut reader)?;
+                    let bits = read_u16(&mut reader)?;
+                    let mut read = 16;
+                    if tag == WAVE_FORMAT_EXTENSIBLE && len >= 26 {
+                        let _extension_len = read_u16(&mut reader)?;
+                        let _valid_bits = read_u16(&mut reader)?;
+                        let _channel_mask = read_u32(&mut reader)?;
+                        // The sub format GUID starts with the actual format tag.
+                        tag = read_u16(&mut reader)?;
+                        read = 26;
+                    }
+                    reader.seek(SeekFrom::Current(i64::from(len) - read + i64::from(len & 1)))?;
+                    let sample_format = match (tag, bits) {
+                        (WAVE_FORMAT_PCM, 8) => WavFormat::U8,
+                        (WAVE_FORMAT_PCM, 16) => WavFormat::I16,
+                        (WAVE_FORMAT_PCM, 24) => WavFormat::I24,
+                        (WAVE_FORMAT_PCM, 32) => WavFormat::I32,
+                        (WAVE_FORMAT_IEEE_FLOAT, 32) => WavFormat::F32,
+                        (WAVE_FORMAT_IEEE_FLOAT, 64) => WavFormat::F64,
+                        _ => return Err(bad_wav("unsupported sample format")),
+                    };
+                    if channels == 0 || sample_rate == 0 {
+                        return Err(bad_wav("no channels"));
+                    }
+                    format = Some((sample_format, channels, sample_rate));
+                }
+                b"data" => {
+                    let (format, channels, sample_rate) = format
+                        .ok_or_else(|| bad_wav("data before format"))?;
+                    let data_start = reader.stream_position()?;
+                    return Ok(WavDecoder {
+                        reader,
+                        format,
+                        channels,
+                        sample_rate,
+                        data_start,
+                        data_len: u64::from(len),
+                        position: 0,
+                        buffer: Vec::new(),
+                    });
+                }
+                _ => {
+                    // Chunks are padded to an even length.
+                    reader.seek(SeekFrom::Current(i64::from(len) + i64::from(len & 1)))?;
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for WavDecoder<R> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn frames(&self) -> Option<u64> {
+        Some(self.data_len / (self.format.bytes() * usize::from(self.channels)) as u64)
+    }
+
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, AudioError> {
+        let frame_len = self.format.bytes() * usize::from(self.channels);
+        let left = ((self.data_len - self.position) / frame_len as u64) as usize;
+        let frames = frames.min(left);
+        self.buffer.resize(frames * frame_len, 0);
+        match self.reader.read_exact(&mut self.buffer) {
+            Ok(()) => {}
+            // Treat a data chunk that is cut off as the end of the sound.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.position = self.data_len;
+                return Ok(0);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.position += self.buffer.len() as u64;
+        let format = self.format;
+        out.extend(self.buffer.chunks(format.bytes()).map(|b| format.sample(b)));
+        Ok(frames)
+    }
+
+    fn rewind(&mut self) -> Result<(), AudioError> {
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+/// Samples decoded in a block that were not read yet.
+#[cfg(any(feature = "lewton", feature = "claxon"))]
+#[derive(Default)]
+struct Pending {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+#[cfg(any(feature = "lewton", feature = "claxon"))]
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.position == self.samples.len()
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+        self.position = 0;
+    }
+
+    fn take(&mut self, channels: u16, frames: usize, out: &mut Vec<f32>) -> usize {
+        let channels = usize::from(channels);
+        let frames = frames.min((self.samples.len() - self.position) / channels);
+        let end = self.position + frames * channels;
+        out.extend_from_slice(&self.samples[self.position..end]);
+        self.position = end;
+        frames
+    }
+}
+
+#[cfg(feature = "lewton")]
+struct OggDecoder {
+    vfs: Vfs,
+    path: std::path::PathBuf,
+    reader: lewton::inside_ogg::OggStreamReader<Box<dyn crate::VfsFile>>,
+    pending: Pending,
+}
+
+#[cfg(feature = "lewton")]
+impl OggDecoder {
+    fn open(vfs: &Vfs, path: &Path) -> Result<OggDecoder, AudioError> {
+        Ok(OggDecoder {
+            vfs: vfs.clone(),
+            path: path.to_owned(),
+            reader: Self::reader(vfs, path)?,
+            pending: Pending::default(),
+        })
+    }
+
+    fn reader(vfs: &Vfs, path: &Path) -> Result<lewton::inside_ogg::OggStreamReader<Box<dyn crate::VfsFile>>, AudioError> {
+        lewton::inside_ogg::OggStreamReader::new(vfs.open(path)?)
+            .map_err(|e| AudioError::Format(e.to_string()))
+    }
+}
+
+#[cfg(feature = "lewton")]
+impl Decoder for OggDecoder {
+    fn channels(&self) -> u16 {
+        u16::from(self.reader.ident_hdr.audio_channels)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.ident_hdr.audio_sample_rate
+    }
+
+    fn frames(&self) -> Option<u64> {
+        None
+    }
+
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, AudioError> {
+        while self.pending.is_empty() {
+            let packet = self.reader.read_dec_packet_itl()
+                .map_err(|e| AudioError::Format(e.to_string()))?;
+            match packet {
+                Some(samples) => {
+                    self.pending.clear();
+                    self.pending.samples.extend(samples.iter().map(|&s| f32::from(s) / 32768.0));
+                }
+                None => return Ok(0),
+            }
+        }
+        Ok(self.pending.take(self.channels(), frames, out))
+    }
+
+    fn rewind(&mut self) -> Result<(), AudioError> {
+        self.reader = Self::reader(&self.vfs, &self.path)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "claxon")]
+struct FlacDecoder {
+    vfs: Vfs,
+    path: std::path::PathBuf,
+    reader: claxon::FlacReader<Box<dyn crate::VfsFile>>,
+    pending: Pending,
+    block: Vec<i32>,
+}
+
+#[cfg(feature = "claxon")]
+impl FlacDecoder {
+    fn open(vfs: &Vfs, path: &Path) -> Result<FlacDecoder, AudioError> {
+        Ok(FlacDecoder {
+            vfs: vfs.clone(),
+            path: path.to_owned(),
+            reader: Self::reader(vfs, path)?,
+            pending: Pending::default(),
+            block: Vec::new(),
+        })
+    }
+
+    fn reader(vfs: &Vfs, path: &Path) -> Result<claxon::FlacReader<Box<dyn crate::VfsFile>>, AudioError> {
+        claxon::FlacReader::new(vfs.open(path)?).map_err(|e| AudioError::Format(e.to_string()))
+    }
+}
+
+#[cfg(feature = "claxon")]
+impl Decoder for FlacDecoder {
+    fn channels(&self) -> u16 {
+        self.reader.streaminfo().channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.streaminfo().sample_rate
+    }
+
+    fn frames(&self) -> Option<u64> {
+        self.reader.streaminfo().samples
+    }
+
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, AudioError> {
+        let channels = self.reader.streaminfo().channels;
+        let scale = 1.0 / (1u64 << (self.reader.streaminfo().bits_per_sample - 1)) as f32;
+        while self.pending.is_empty() {
+            let buffer = std::mem::take(&mut self.block);
+            let block = self.reader.blocks().read_next_or_eof(buffer)
+                .map_err(|e| AudioError::Format(e.to_string()))?;
+            match block {
+                Some(block) => {
+                    self.pending.clear();
+                    for i in 0..block.duration() {
+                        for channel in 0..channels {
+                            self.pending.samples.push(block.sample(channel, i) as f32 * scale);
+                        }
+                    }
+                    self.block = block.into_buffer();
+                }
+                None => return Ok(0),
+            }
+        }
+        Ok(self.pending.take(self.channels(), frames, out))
+    }
+
+    fn rewind(&mut self) -> Result<(), AudioError> {
+        self.reader = Self::reader(&self.vfs, &self.path)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+use crate::ReplayError;
+
+/// Why `try_run` failed to start the game.
+#[derive(Debug)]
+pub enum RunError {
+    CreateWindow(winit::error::OsError),
+    LoadIcon(PathBuf, Box<dyn Error + Send + Sync>),
+    /// No graphics adapter can draw to the window, usually because of
+    /// missing or outdated drivers.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    LoadReplay(PathBuf, ReplayError),
+    CreateRecording(PathBuf, io::Error),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::CreateWindow(e) => write!(f, "failed to create window: {}", e),
+            RunError::LoadIcon(path, e) => {
+                write!(f, "failed to load window icon {}: {}", path.display(), e)
+            }
+            RunError::NoAdapter => write!(
+                f,
+                "no compatible graphics adapter found, make sure drivers with Vulkan, \
+                 Metal or DirectX 12 support are installed",
+            ),
+            RunError::RequestDevice(e) => write!(f, "failed to open graphics device: {}", e),
+            RunError::LoadReplay(path, e) => write!(f, "failed to load {}: {}", path.display(), e),
+            RunError::CreateRecording(path, e) => {
+                write!(f, "failed to create {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunError::CreateWindow(e) => Some(e),
+            RunError::LoadIcon(_, e) => Some(&**e),
+            RunError::NoAdapter => None,
+            RunError::RequestDevice(e) => Some(e),
+            RunError::LoadReplay(_, e) => Some(e),
+            RunError::CreateRecording(_, e) => Some(e),
+        }
+    }
+}
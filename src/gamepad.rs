@@ -0,0 +1,535 @@
+use std::{cell::RefCell, rc::Rc};
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct GamepadId(pub usize);
+
+#[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
+#[repr(u32)]
+pub enum GamepadButton {
+    /// The bottom face button (A on Xbox, Cross on PlayStation).
+    South,
+    /// The right face button (B on Xbox, Circle on PlayStation).
+    East,
+    /// The top face button (Y on Xbox, Triangle on PlayStation).
+    North,
+    /// The left face button (X on Xbox, Square on PlayStation).
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    /// The vendor button in the middle of the pad.
+    Mode,
+    /// Pressing down the left stick.
+    LeftStick,
+    /// Pressing down the right stick.
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    pub const ALL: &'static [GamepadButton] = &[
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::North,
+        GamepadButton::West,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::Select,
+        GamepadButton::Start,
+        GamepadButton::Mode,
+        GamepadButton::LeftStick,
+        GamepadButton::RightStick,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum GamepadAxis {
+    LeftStickX,
+    /// Positive values point up.
+    LeftStickY,
+    RightStickX,
+    /// Positive values point up.
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+const AXIS_COUNT: usize = 6;
+
+impl GamepadAxis {
+    pub const ALL: &'static [GamepadAxis] = &[
+        GamepadAxis::LeftStickX,
+        GamepadAxis::LeftStickY,
+        GamepadAxis::RightStickX,
+        GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            GamepadAxis::LeftStickX => 0,
+            GamepadAxis::LeftStickY => 1,
+            GamepadAxis::RightStickX => 2,
+            GamepadAxis::RightStickY => 3,
+            GamepadAxis::LeftTrigger => 4,
+            GamepadAxis::RightTrigger => 5,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    ButtonPressed(GamepadId, GamepadButton),
+    ButtonReleased(GamepadId, GamepadButton),
+    /// Sticks report values in `-1.0..=1.0`, triggers in `0.0..=1.0`.
+    AxisChanged(GamepadId, GamepadAxis, f32),
+}
+
+/// Source of gamepad events, polled once per frame before running updates.
+///
+/// The default backend is picked by `default_backend`, games and tests can
+/// replace it with `Ctx::set_gamepad_backend`.
+pub trait GamepadBackend {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+}
+
+/// Backend that never reports any gamepads.
+pub struct NoGamepads;
+
+impl GamepadBackend for NoGamepads {
+    fn poll(&mut self, _events: &mut Vec<GamepadEvent>) {}
+}
+
+/// Backend that reports scripted events, for tests and demos without a pad.
+///
+/// Clones share one event queue: keep a clone to drive the pads after
+/// handing the backend to `Ctx::set_gamepad_backend`.
+#[derive(Clone, Default)]
+pub struct FakeGamepads {
+    events: Rc<RefCell<Vec<GamepadEvent>>>,
+}
+
+impl FakeGamepads {
+    pub fn new() -> FakeGamepads {
+        FakeGamepads::default()
+    }
+
+    /// Queues an event for the next poll.
+    pub fn push(&self, event: GamepadEvent) {
+        self.events.borrow_mut().push(event);
+    }
+
+    pub fn connect(&self, id: GamepadId) {
+        self.push(GamepadEvent::Connected(id));
+    }
+
+    pub fn disconnect(&self, id: GamepadId) {
+        self.push(GamepadEvent::Disconnected(id));
+    }
+
+    pub fn press(&self, id: GamepadId, button: GamepadButton) {
+        self.push(GamepadEvent::ButtonPressed(id, button));
+    }
+
+    pub fn release(&self, id: GamepadId, button: GamepadButton) {
+        self.push(GamepadEvent::ButtonReleased(id, button));
+    }
+
+    pub fn set_axis(&self, id: GamepadId, axis: GamepadAxis, value: f32) {
+        self.push(GamepadEvent::AxisChanged(id, axis, value));
+    }
+}
+
+impl GamepadBackend for FakeGamepads {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        events.append(&mut self.events.borrow_mut());
+    }
+}
+
+#[cfg(feature = "gilrs")]
+pub struct GilrsBackend {
+    gilrs: gilrs::Gilrs,
+    connected: Vec<GamepadId>,
+}
+
+#[cfg(feature = "gilrs")]
+impl GilrsBackend {
+    pub fn new() -> Result<GilrsBackend, gilrs::Error> {
+        let gilrs = gilrs::Gilrs::new()?;
+        let connected = gilrs.gamepads().map(|(id, _)| GamepadId(id.into())).collect();
+        Ok(GilrsBackend { gilrs, connected })
+    }
+}
+
+#[cfg(feature = "gilrs")]
+impl GamepadBackend for GilrsBackend {
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        use gilrs::{Axis, Button, EventType};
+
+        fn button(button: Button) -> Option<GamepadButton> {
+            Some(match button {
+                Button::South => GamepadButton::South,
+                Button::East => GamepadButton::East,
+                Button::North => GamepadButton::North,
+                Button::West => GamepadButton::West,
+                Button::LeftTrigger => GamepadButton::LeftBumper,
+                Button::RightTrigger => GamepadButton::RightBumper,
+                Button::Select => GamepadButton::Select,
+                Button::Start => GamepadButton::Start,
+                Button::Mode => GamepadButton::Mode,
+                Button::LeftThumb => GamepadButton::LeftStick,
+                Button::RightThumb => GamepadButton::RightStick,
+                Button::DPadUp => GamepadButton::DPadUp,
+                Button::DPadDown => GamepadButton::DPadDown,
+                Button::DPadLeft => GamepadButton::DPadLeft,
+                Button::DPadRight => GamepadButton::DPadRight,
+                _ => return None,
+            })
+        }
+
+        fn axis(axis: Axis) -> Option<GamepadAxis> {
+            Some(match axis {
+                Axis::LeftStickX => GamepadAxis::LeftStickX,
+                Axis::LeftStickY => GamepadAxis::LeftStickY,
+                Axis::RightStickX => GamepadAxis::RightStickX,
+                Axis::RightStickY => GamepadAxis::RightStickY,
+                Axis::LeftZ => GamepadAxis::LeftTrigger,
+                Axis::RightZ => GamepadAxis::RightTrigger,
+                _ => return None,
+            })
+        }
+
+        for id in self.connected.drain(..) {
+            events.push(GamepadEvent::Connected(id));
+        }
+
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let id = GamepadId(id.into());
+            match event {
+                EventType::Connected => events.push(GamepadEvent::Connected(id)),
+                EventType::Disconnected => events.push(GamepadEvent::Disconnected(id)),
+                EventType::ButtonPressed(b, _) => if let Some(b) = button(b) {
+                    events.push(GamepadEvent::ButtonPressed(id, b));
+                },
+                EventType::ButtonReleased(b, _) => if let Some(b) = button(b) {
+                    events.push(GamepadEvent::ButtonReleased(id, b));
+                },
+                // analog triggers are reported as buttons with a value
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => {
+                    events.push(GamepadEvent::AxisChanged(id, GamepadAxis::LeftTrigger, value));
+                }
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => {
+                    events.push(GamepadEvent::AxisChanged(id, GamepadAxis::RightTrigger, value));
+                }
+                EventType::AxisChanged(a, value, _) => if let Some(a) = axis(a) {
+                    events.push(GamepadEvent::AxisChanged(id, a, value));
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+pub(crate) fn default_backend() -> Box<dyn GamepadBackend> {
+    #[cfg(feature = "gilrs")]
+    {
+        match GilrsBackend::new() {
+            Ok(backend) => return Box::new(backend),
+            Err(e) => eprintln!("failed to initialize gamepads: {}", e),
+        }
+    }
+    Box::new(NoGamepads)
+}
+
+/// Dead zones are clamped to `0.0..=0.99` when set, as a full 1.0 would leave
+/// no range to scale the rest of the axis into.
+#[derive(Debug, Clone, Copy)]
+pub struct DeadZone {
+    /// Radial dead zone applied to each stick.
+    pub stick: f32,
+    /// Dead zone applied to each trigger.
+    pub trigger: f32,
+}
+
+const MAX_DEAD_ZONE: f32 = 0.99;
+
+impl DeadZone {
+    fn clamped(self) -> DeadZone {
+        let clamp = |v: f32| if v.is_nan() { 0.0 } else { v.clamp(0.0, MAX_DEAD_ZONE) };
+        DeadZone {
+            stick: clamp(self.stick),
+            trigger: clamp(self.trigger),
+        }
+    }
+}
+
+impl Default for DeadZone {
+    fn default() -> DeadZone {
+        DeadZone {
+            stick: 0.15,
+            trigger: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Gamepad {
+    buttons: u32,
+    axes: [f32; AXIS_COUNT],
+    dead_zone: DeadZone,
+}
+
+impl Gamepad {
+    fn new(dead_zone: DeadZone) -> Gamepad {
+        Gamepad {
+            buttons: 0,
+            axes: [0.0; AXIS_COUNT],
+            dead_zone,
+        }
+    }
+
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        (self.buttons >> button as u32) & 1 != 0
+    }
+
+    /// Axis value with dead zones applied.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        match axis {
+            GamepadAxis::LeftStickX => self.left_stick().0,
+            GamepadAxis::LeftStickY => self.left_stick().1,
+            GamepadAxis::RightStickX => self.right_stick().0,
+            GamepadAxis::RightStickY => self.right_stick().1,
+            GamepadAxis::LeftTrigger |
+            GamepadAxis::RightTrigger => {
+                let value = self.raw_axis(axis);
+                if value < self.dead_zone.trigger {
+                    0.0
+                } else {
+                    (value - self.dead_zone.trigger) / (1.0 - self.dead_zone.trigger)
+                }
+            }
+        }
+    }
+
+    /// Axis value as reported by the device.
+    pub fn raw_axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis.index()]
+    }
+
+    pub fn left_stick(&self) -> (f32, f32) {
+        self.stick(GamepadAxis::LeftStickX, GamepadAxis::LeftStickY)
+    }
+
+    pub fn right_stick(&self) -> (f32, f32) {
+        self.stick(GamepadAxis::RightStickX, GamepadAxis::RightStickY)
+    }
+
+    fn stick(&self, x: GamepadAxis, y: GamepadAxis) -> (f32, f32) {
+        let (x, y) = (self.raw_axis(x), self.raw_axis(y));
+        let len = (x * x + y * y).sqrt();
+        if len <= self.dead_zone.stick {
+            return (0.0, 0.0);
+        }
+        let scaled = ((len - self.dead_zone.stick) / (1.0 - self.dead_zone.stick)).min(1.0);
+        (x / len * scaled, y / len * scaled)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Gamepads {
+    pads: Vec<(GamepadId, Gamepad)>,
+    events: Vec<GamepadEvent>,
+    dead_zone: DeadZone,
+}
+
+impl Gamepads {
+    pub(crate) fn get(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.pads.iter().find(|(i, _)| *i == id).map(|(_, pad)| pad)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.pads.iter().map(|(id, pad)| (*id, pad))
+    }
+
+    pub(crate) fn events(&self) -> &[GamepadEvent] {
+        &self.events
+    }
+
+    pub(crate) fn set_dead_zone(&mut self, dead_zone: DeadZone) {
+        let dead_zone = dead_zone.clamped();
+        self.dead_zone = dead_zone;
+        for (_, pad) in &mut self.pads {
+            pad.dead_zone = dead_zone;
+        }
+    }
+
+    pub(crate) fn handle_event(&mut self, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Connected(id) => {
+                if self.get(id).is_none() {
+                    self.pads.push((id, Gamepad::new(self.dead_zone)));
+                }
+            }
+            GamepadEvent::Disconnected(id) => {
+                self.pads.retain(|(i, _)| *i != id);
+            }
+            GamepadEvent::ButtonPressed(id, button) => {
+                if let Some(pad) = self.get_mut(id) {
+                    pad.buttons |= 1 << button as u32;
+                }
+            }
+            GamepadEvent::ButtonReleased(id, button) => {
+                if let Some(pad) = self.get_mut(id) {
+                    pad.buttons &= !(1 << button as u32);
+                }
+            }
+            GamepadEvent::AxisChanged(id, axis, value) => {
+                if let Some(pad) = self.get_mut(id) {
+                    pad.axes[axis.index()] = value;
+                }
+            }
+        }
+        self.events.push(event);
+    }
+
+    pub(crate) fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        for &(id, ref pad) in &self.pads {
+            events.push(GamepadEvent::Connected(id));
+            for &button in GamepadButton::ALL {
+                if pad.is_pressed(button) {
+                    events.push(GamepadEvent::ButtonPressed(id, button));
+                }
+            }
+            for &axis in GamepadAxis::ALL {
+                events.push(GamepadEvent::AxisChanged(id, axis, pad.raw_axis(axis)));
+            }
+        }
+        events
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.pads.clear();
+        self.events.clear();
+    }
+
+    fn get_mut(&mut self, id: GamepadId) -> Option<&mut Gamepad> {
+        self.pads.iter_mut().find(|(i, _)| *i == id).map(|(_, pad)| pad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: GamepadId = GamepadId(0);
+
+    fn run(backend: &FakeGamepads, gamepads: &mut Gamepads) {
+        let mut events = Vec::new();
+        backend.clone().poll(&mut events);
+        gamepads.clear_events();
+        for event in events {
+            gamepads.handle_event(event);
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let backend = FakeGamepads::new();
+        let mut gamepads = Gamepads::default();
+        backend.connect(PAD);
+        backend.connect(GamepadId(1));
+        run(&backend, &mut gamepads);
+        assert_eq!(gamepads.iter().count(), 2);
+        assert_eq!(gamepads.events().len(), 2);
+
+        backend.disconnect(PAD);
+        run(&backend, &mut gamepads);
+        assert!(gamepads.get(PAD).is_none());
+        assert!(gamepads.get(GamepadId(1)).is_some());
+        assert_eq!(gamepads.events(), &[GamepadEvent::Disconnected(PAD)]);
+
+        run(&backend, &mut gamepads);
+        assert!(gamepads.events().is_empty());
+    }
+
+    #[test]
+    fn buttons() {
+        let backend = FakeGamepads::new();
+        let mut gamepads = Gamepads::default();
+        backend.press(PAD, GamepadButton::South);
+        backend.connect(PAD);
+        backend.press(PAD, GamepadButton::DPadRight);
+        run(&backend, &mut gamepads);
+        let pad = gamepads.get(PAD).unwrap();
+        assert!(!pad.is_pressed(GamepadButton::South), "events for unknown pads are ignored");
+        assert!(pad.is_pressed(GamepadButton::DPadRight));
+
+        backend.release(PAD, GamepadButton::DPadRight);
+        run(&backend, &mut gamepads);
+        assert!(!gamepads.get(PAD).unwrap().is_pressed(GamepadButton::DPadRight));
+    }
+
+    #[test]
+    fn stick_dead_zone() {
+        let backend = FakeGamepads::new();
+        let mut gamepads = Gamepads::default();
+        gamepads.set_dead_zone(DeadZone { stick: 0.2, trigger: 0.1 });
+        backend.connect(PAD);
+        backend.set_axis(PAD, GamepadAxis::LeftStickX, 0.1);
+        backend.set_axis(PAD, GamepadAxis::LeftStickY, 0.1);
+        run(&backend, &mut gamepads);
+        assert_eq!(gamepads.get(PAD).unwrap().left_stick(), (0.0, 0.0));
+
+        backend.set_axis(PAD, GamepadAxis::LeftStickX, 0.6);
+        backend.set_axis(PAD, GamepadAxis::LeftStickY, 0.0);
+        run(&backend, &mut gamepads);
+        let pad = gamepads.get(PAD).unwrap();
+        assert!(close(pad.axis(GamepadAxis::LeftStickX), 0.5));
+        assert!(close(pad.raw_axis(GamepadAxis::LeftStickX), 0.6));
+
+        backend.set_axis(PAD, GamepadAxis::LeftStickX, 1.0);
+        backend.set_axis(PAD, GamepadAxis::LeftStickY, 1.0);
+        run(&backend, &mut gamepads);
+        let (x, y) = gamepads.get(PAD).unwrap().left_stick();
+        assert!(close((x * x + y * y).sqrt(), 1.0));
+
+        backend.set_axis(PAD, GamepadAxis::RightTrigger, 0.55);
+        run(&backend, &mut gamepads);
+        assert!(close(gamepads.get(PAD).unwrap().axis(GamepadAxis::RightTrigger), 0.5));
+    }
+
+    #[test]
+    fn dead_zone_is_clamped() {
+        let backend = FakeGamepads::new();
+        let mut gamepads = Gamepads::default();
+        backend.connect(PAD);
+        run(&backend, &mut gamepads);
+        gamepads.set_dead_zone(DeadZone { stick: 1.0, trigger: 2.0 });
+        backend.set_axis(PAD, GamepadAxis::LeftStickX, 1.0);
+        backend.set_axis(PAD, GamepadAxis::LeftTrigger, 1.0);
+        run(&backend, &mut gamepads);
+        let pad = gamepads.get(PAD).unwrap();
+        assert!(close(pad.axis(GamepadAxis::LeftStickX), 1.0));
+        assert!(close(pad.axis(GamepadAxis::LeftTrigger), 1.0));
+    }
+}
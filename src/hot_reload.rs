@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use crate::{JobQueue, Texture, TextureProcessors, Vfs};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Where a texture was loaded from, so it can be loaded again.
+pub(crate) struct TextureSource {
+    pub(crate) path: PathBuf,
+    pub(crate) processors: TextureProcessors,
+    /// Modification time from before the file was read.
+    pub(crate) modified: Option<SystemTime>,
+}
+
+impl TextureSource {
+    pub(crate) fn new(
+        vfs: &Vfs,
+        path: &Path,
+        processors: &TextureProcessors,
+    ) -> TextureSource {
+        TextureSource {
+            path: path.to_owned(),
+            processors: processors.clone(),
+            modified: vfs.modified(path),
+        }
+    }
+}
+
+struct Watched {
+    index: usize,
+    source: TextureSource,
+    reloading: bool,
+}
+
+/// Polls the files of loaded textures and reloads them when they change.
+/// Polling is cheap enough for a development build and works the same
+/// everywhere, unlike file system notifications.
+pub(crate) struct HotReload {
+    enabled: bool,
+    watched: Vec<Watched>,
+    next_poll: Instant,
+}
+
+impl HotReload {
+    pub(crate) fn new(enabled: bool) -> HotReload {
+        HotReload {
+            enabled,
+            watched: Vec::new(),
+            next_poll: Instant::now() + POLL_INTERVAL,
+        }
+    }
+
+    pub(crate) fn watch(&mut self, texture: Texture, source: TextureSource) {
+        if self.enabled {
+            self.watched.push(Watched {
+                index: texture.index,
+                source,
+                reloading: false,
+            });
+        }
+    }
+
+    /// When `poll` wants to run next, `None` if nothing is watched.
+    pub(crate) fn next_poll(&self) -> Option<Instant> {
+        if self.watched.is_empty() {
+            None
+        } else {
+            Some(self.next_poll)
+        }
+    }
+
+    /// Starts reloading textures whose files changed since they were read.
+    pub(crate) fn poll(&mut self, job_queue: &JobQueue) {
+        let now = Instant::now();
+        if self.watched.is_empty() || now < self.next_poll {
+            return;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+        for watched in &mut self.watched {
+            if watched.reloading {
+                continue;
+            }
+            let modified = job_queue.vfs().modified(&watched.source.path);
+            if modified.is_some() && modified != watched.source.modified {
+                let texture = Texture { index: watched.index };
+                let path = watched.source.path.clone();
+                // not queued while quitting, there is no result to wait for then
+                if job_queue.reload_texture(texture, path, watched.source.processors.clone()) {
+                    watched.source.modified = modified;
+                    watched.reloading = true;
+                }
+            }
+        }
+    }
+
+    /// Called once a reload finished, whether it worked or not.
+    pub(crate) fn reloaded(&mut self, texture: Texture) {
+        for watched in &mut self.watched {
+            if watched.index == texture.index {
+                watched.reloading = false;
+            }
+        }
+    }
+}
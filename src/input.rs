@@ -1,3 +1,23 @@
+use crate::gamepad::{Gamepad, GamepadEvent, GamepadId, Gamepads};
+
+/// A single change to the input state. Everything that modifies `Input`
+/// goes through these so that it can be recorded and replayed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum InputEvent {
+    KeyPressed(Key),
+    KeyReleased(Key),
+    ModifiersChanged(Modifiers),
+    MousePressed(MouseButton),
+    MouseReleased(MouseButton),
+    MouseMoved(Option<(u32, u32)>),
+    MouseScrolled(f32),
+    ReleaseAll,
+    Gamepad(GamepadEvent),
+    Touch(Touch),
+    /// Drops the events so far, for frames without an update while paused.
+    ClearEvents,
+}
+
 #[derive(Debug, Hash, Ord, PartialOrd, PartialEq, Eq, Clone, Copy)]
 #[repr(u32)]
 pub enum Key {
@@ -199,6 +219,179 @@ pub enum Key {
     Cut,
 }
 
+impl Key {
+    pub const ALL: &'static [Key] = &[
+        Key::Key1,
+        Key::Key2,
+        Key::Key3,
+        Key::Key4,
+        Key::Key5,
+        Key::Key6,
+        Key::Key7,
+        Key::Key8,
+        Key::Key9,
+        Key::Key0,
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+        Key::Escape,
+        Key::F1,
+        Key::F2,
+        Key::F3,
+        Key::F4,
+        Key::F5,
+        Key::F6,
+        Key::F7,
+        Key::F8,
+        Key::F9,
+        Key::F10,
+        Key::F11,
+        Key::F12,
+        Key::F13,
+        Key::F14,
+        Key::F15,
+        Key::F16,
+        Key::F17,
+        Key::F18,
+        Key::F19,
+        Key::F20,
+        Key::F21,
+        Key::F22,
+        Key::F23,
+        Key::F24,
+        Key::Snapshot,
+        Key::Scroll,
+        Key::Pause,
+        Key::Insert,
+        Key::Home,
+        Key::Delete,
+        Key::End,
+        Key::PageDown,
+        Key::PageUp,
+        Key::Left,
+        Key::Up,
+        Key::Right,
+        Key::Down,
+        Key::Back,
+        Key::Return,
+        Key::Space,
+        Key::Compose,
+        Key::Caret,
+        Key::Numlock,
+        Key::Numpad0,
+        Key::Numpad1,
+        Key::Numpad2,
+        Key::Numpad3,
+        Key::Numpad4,
+        Key::Numpad5,
+        Key::Numpad6,
+        Key::Numpad7,
+        Key::Numpad8,
+        Key::Numpad9,
+        Key::NumpadAdd,
+        Key::NumpadDivide,
+        Key::NumpadDecimal,
+        Key::NumpadComma,
+        Key::NumpadEnter,
+        Key::NumpadEquals,
+        Key::NumpadMultiply,
+        Key::NumpadSubtract,
+        Key::AbntC1,
+        Key::AbntC2,
+        Key::Apostrophe,
+        Key::Apps,
+        Key::Asterisk,
+        Key::At,
+        Key::Ax,
+        Key::Backslash,
+        Key::Calculator,
+        Key::Capital,
+        Key::Colon,
+        Key::Comma,
+        Key::Convert,
+        Key::Equals,
+        Key::Grave,
+        Key::Kana,
+        Key::Kanji,
+        Key::LAlt,
+        Key::LBracket,
+        Key::LControl,
+        Key::LShift,
+        Key::LWin,
+        Key::Mail,
+        Key::MediaSelect,
+        Key::MediaStop,
+        Key::Minus,
+        Key::Mute,
+        Key::MyComputer,
+        Key::NavigateForward,
+        Key::NavigateBackward,
+        Key::NextTrack,
+        Key::NoConvert,
+        Key::OEM102,
+        Key::Period,
+        Key::PlayPause,
+        Key::Plus,
+        Key::Power,
+        Key::PrevTrack,
+        Key::RAlt,
+        Key::RBracket,
+        Key::RControl,
+        Key::RShift,
+        Key::RWin,
+        Key::Semicolon,
+        Key::Slash,
+        Key::Sleep,
+        Key::Stop,
+        Key::Sysrq,
+        Key::Tab,
+        Key::Underline,
+        Key::Unlabeled,
+        Key::VolumeDown,
+        Key::VolumeUp,
+        Key::Wake,
+        Key::WebBack,
+        Key::WebFavorites,
+        Key::WebForward,
+        Key::WebHome,
+        Key::WebRefresh,
+        Key::WebSearch,
+        Key::WebStop,
+        Key::Yen,
+        Key::Copy,
+        Key::Paste,
+        Key::Cut,
+    ];
+
+    /// Looks up a key by its variant name, e.g. `"Space"` or `"F1"`.
+    pub fn from_name(name: &str) -> Option<Key> {
+        Key::ALL.iter().copied().find(|key| format!("{:?}", key) == name)
+    }
+}
+
 impl From<winit::event::VirtualKeyCode> for Key {
     fn from(key: winit::event::VirtualKeyCode) -> Key {
         match key {
@@ -369,14 +562,96 @@ impl From<winit::event::VirtualKeyCode> for Key {
     }
 }
 
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum MouseButton {
+    Left,
+    Right,
+}
+
+/// State of the modifier keys, either side counts.
+#[derive(Debug, Default, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false, logo: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, ..Modifiers::NONE };
+    pub const CTRL: Modifiers = Modifiers { ctrl: true, ..Modifiers::NONE };
+    pub const ALT: Modifiers = Modifiers { alt: true, ..Modifiers::NONE };
+    pub const LOGO: Modifiers = Modifiers { logo: true, ..Modifiers::NONE };
+
+    pub fn is_empty(self) -> bool {
+        self == Modifiers::NONE
+    }
+}
+
+impl From<winit::event::ModifiersState> for Modifiers {
+    fn from(state: winit::event::ModifiersState) -> Modifiers {
+        Modifiers {
+            shift: state.shift(),
+            ctrl: state.ctrl(),
+            alt: state.alt(),
+            logo: state.logo(),
+        }
+    }
+}
+
+impl std::ops::BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        Modifiers {
+            shift: self.shift || other.shift,
+            ctrl: self.ctrl || other.ctrl,
+            alt: self.alt || other.alt,
+            logo: self.logo || other.logo,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KeyEvent {
+    Pressed(Key),
+    /// The OS repeated a key that is being held down.
+    Repeated(Key),
+    Released(Key),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    /// The touch is still on the screen but did not move since the
+    /// previous update.
+    Held,
+    Ended,
+    Cancelled,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Touch {
+    pub id: u64,
+    pub phase: TouchPhase,
+    pub pos: (u32, u32),
+}
+
 #[derive(Default)]
 pub struct Input {
     key_state: [u64; 4],
     pressed_list: Vec<Key>,
+    key_events: Vec<KeyEvent>,
+    modifiers: Modifiers,
     mouse_pos: Option<(u32, u32)>,
     mouse_left_pressed: bool,
     mouse_right_pressed: bool,
     scroll_pos: f32,
+    gamepads: Gamepads,
+    touches: Vec<Touch>,
+    touch_events: Vec<Touch>,
 }
 
 impl Input {
@@ -392,6 +667,33 @@ impl Input {
         self.mouse_right_pressed
     }
 
+    pub fn is_mouse_pressed(&self, button: MouseButton) -> bool {
+        match button {
+            MouseButton::Left => self.mouse_left_pressed,
+            MouseButton::Right => self.mouse_right_pressed,
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Key presses, repeats and releases since the previous update.
+    pub fn key_events(&self) -> &[KeyEvent] {
+        &self.key_events
+    }
+
+    /// Whether the key went down since the previous update.
+    pub fn was_pressed(&self, key: Key) -> bool {
+        self.key_events.contains(&KeyEvent::Pressed(key))
+    }
+
+    /// Whether the key went down or was repeated by the OS since the
+    /// previous update, useful for menus that scroll while a key is held.
+    pub fn was_pressed_or_repeated(&self, key: Key) -> bool {
+        self.was_pressed(key) || self.key_events.contains(&KeyEvent::Repeated(key))
+    }
+
     pub fn mouse_pos(&self) -> Option<(u32, u32)> {
         self.mouse_pos
     }
@@ -400,10 +702,85 @@ impl Input {
         self.scroll_pos
     }
 
+    /// Touches currently on the screen, plus the ones that ended since the
+    /// previous update.
+    pub fn touches(&self) -> &[Touch] {
+        &self.touches
+    }
+
+    /// Every touch change since the previous update in the order it
+    /// happened, so a tap that starts and ends within one update shows up
+    /// as both `Started` and `Ended`.
+    pub fn touch_events(&self) -> &[Touch] {
+        &self.touch_events
+    }
+
+    pub fn gamepad(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(id)
+    }
+
+    pub fn gamepads(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.gamepads.iter()
+    }
+
+    /// Gamepad events received since the previous update.
+    pub fn gamepad_events(&self) -> &[GamepadEvent] {
+        self.gamepads.events()
+    }
+
+    pub(crate) fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::KeyPressed(key) => self.press_key(key),
+            InputEvent::KeyReleased(key) => self.release_key(key),
+            InputEvent::ModifiersChanged(modifiers) => self.set_modifiers(modifiers),
+            InputEvent::MousePressed(MouseButton::Left) => self.set_mouse_left_press(true),
+            InputEvent::MouseReleased(MouseButton::Left) => self.set_mouse_left_press(false),
+            InputEvent::MousePressed(MouseButton::Right) => self.set_mouse_right_press(true),
+            InputEvent::MouseReleased(MouseButton::Right) => self.set_mouse_right_press(false),
+            InputEvent::MouseMoved(pos) => self.set_mouse_pos(pos),
+            InputEvent::MouseScrolled(scroll) => self.add_mouse_scroll(scroll),
+            InputEvent::ReleaseAll => self.release_all(),
+            InputEvent::Gamepad(event) => self.gamepads.handle_event(event),
+            InputEvent::Touch(touch) => self.update_touch(touch),
+            InputEvent::ClearEvents => self.clear_events(),
+        }
+    }
+
+    /// Events that rebuild the current state when applied to a reset input.
+    pub(crate) fn snapshot(&self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        events.extend(self.pressed_list.iter().map(|&key| InputEvent::KeyPressed(key)));
+        events.push(InputEvent::ModifiersChanged(self.modifiers));
+        if self.mouse_left_pressed {
+            events.push(InputEvent::MousePressed(MouseButton::Left));
+        }
+        if self.mouse_right_pressed {
+            events.push(InputEvent::MousePressed(MouseButton::Right));
+        }
+        events.push(InputEvent::MouseMoved(self.mouse_pos));
+        events.push(InputEvent::MouseScrolled(self.scroll_pos));
+        events.extend(self.gamepads.snapshot().into_iter().map(InputEvent::Gamepad));
+        events.extend(self.touches.iter().map(|&touch| InputEvent::Touch(touch)));
+        events
+    }
+
+    /// Clears all state except for settings such as gamepad dead zones.
+    pub(crate) fn reset(&mut self) {
+        self.release_all();
+        self.pressed_list.clear();
+        self.key_events.clear();
+        self.touch_events.clear();
+        self.scroll_pos = 0.0;
+        self.gamepads.reset();
+    }
+
     pub(crate) fn press_key(&mut self, key: Key) {
         if !self.get_bit(key) {
             self.set_bit(key, true);
             self.pressed_list.push(key);
+            self.key_events.push(KeyEvent::Pressed(key));
+        } else {
+            self.key_events.push(KeyEvent::Repeated(key));
         }
     }
 
@@ -411,11 +788,19 @@ impl Input {
         if self.get_bit(key) {
             self.set_bit(key, false);
             self.pressed_list.retain(|&k| k != key);
+            self.key_events.push(KeyEvent::Released(key));
         }
     }
 
+    pub(crate) fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.modifiers = modifiers;
+    }
+
     pub(crate) fn release_all(&mut self) {
         self.key_state.copy_from_slice(&[0, 0, 0, 0]);
+        self.pressed_list.clear();
+        self.touches.clear();
+        self.modifiers = Modifiers::NONE;
         self.mouse_left_pressed = false;
         self.mouse_right_pressed = false;
         self.mouse_pos = None;
@@ -437,6 +822,33 @@ impl Input {
         self.scroll_pos += scroll;
     }
 
+    pub(crate) fn gamepads_mut(&mut self) -> &mut Gamepads {
+        &mut self.gamepads
+    }
+
+    pub(crate) fn clear_events(&mut self) {
+        self.key_events.clear();
+        self.touch_events.clear();
+        self.touches.retain(|t| t.phase != TouchPhase::Ended && t.phase != TouchPhase::Cancelled);
+        for touch in &mut self.touches {
+            touch.phase = TouchPhase::Held;
+        }
+        self.gamepads.clear_events();
+    }
+
+    fn update_touch(&mut self, touch: Touch) {
+        self.touch_events.push(touch);
+        match self.touches.iter_mut().find(|t| t.id == touch.id) {
+            // a touch that started and moved within one update is still
+            // reported as started
+            Some(t) if t.phase == TouchPhase::Started && touch.phase == TouchPhase::Moved => {
+                t.pos = touch.pos;
+            }
+            Some(t) => *t = touch,
+            None => self.touches.push(touch),
+        }
+    }
+
     fn get_bit(&self, idx: Key) -> bool {
         let idx = idx as u32;
         let entry = self.key_state[(idx / 64) as usize];
@@ -453,3 +865,51 @@ impl Input {
         *entry = (*entry & mask) | state;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_list_matches_discriminants() {
+        for (i, &key) in Key::ALL.iter().enumerate() {
+            assert_eq!(key as usize, i, "{:?}", key);
+        }
+        assert!(Key::ALL.len() <= 64 * 4);
+    }
+
+    #[test]
+    fn tap_within_one_update() {
+        let touch = |phase, pos| InputEvent::Touch(Touch { id: 1, phase, pos });
+        let mut input = Input::default();
+        input.apply(touch(TouchPhase::Started, (1, 1)));
+        input.apply(touch(TouchPhase::Moved, (2, 2)));
+        input.apply(touch(TouchPhase::Ended, (3, 3)));
+        let phases = input.touch_events().iter().map(|t| t.phase).collect::<Vec<_>>();
+        assert_eq!(phases, [TouchPhase::Started, TouchPhase::Moved, TouchPhase::Ended]);
+        assert_eq!(input.touches(), &[Touch { id: 1, phase: TouchPhase::Ended, pos: (3, 3) }]);
+
+        input.clear_events();
+        assert!(input.touch_events().is_empty());
+        assert!(input.touches().is_empty());
+    }
+
+    #[test]
+    fn held_touches() {
+        let mut input = Input::default();
+        input.apply(InputEvent::Touch(Touch { id: 1, phase: TouchPhase::Started, pos: (1, 1) }));
+        input.apply(InputEvent::Touch(Touch { id: 1, phase: TouchPhase::Moved, pos: (2, 2) }));
+        assert_eq!(input.touches()[0].phase, TouchPhase::Started);
+        input.clear_events();
+        assert_eq!(input.touches(), &[Touch { id: 1, phase: TouchPhase::Held, pos: (2, 2) }]);
+        assert!(input.touch_events().is_empty());
+    }
+
+    #[test]
+    fn key_names_round_trip() {
+        for &key in Key::ALL {
+            assert_eq!(Key::from_name(&format!("{:?}", key)), Some(key));
+        }
+        assert_eq!(Key::from_name("NotAKey"), None);
+    }
+}
@@ -1,42 +1,153 @@
+mod action;
+mod assets;
+mod audio;
+mod clipboard;
+mod config;
+mod decode;
+mod error;
+mod gamepad;
+mod hot_reload;
 mod input;
 mod queue;
 mod renderer;
+mod replay;
+mod stats;
 mod texture;
 mod timer;
+mod vfs;
 mod wgpu_render;
+mod window;
 
 use std::{
-    sync::mpsc::{Receiver, TryRecvError},
+    any::Any,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, TryRecvError},
+    },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 use winit::{
-    event::*,
+    event::{self, *},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::Window,
 };
 use crate::{
     wgpu_render::WgpuState,
+    hot_reload::HotReload,
     timer::Timer,
+    input::InputEvent,
     queue::FinishedJob,
+    replay::Recorder,
+    stats::FrameHistory,
+    window::WindowCommand,
 };
 pub use crate::{
-    input::{Input, Key},
-    queue::{JobQueue, JobToken},
+    action::{ActionMap, AxisBinding, Binding, BindingsError, Button, Conflict},
+    assets::{Assets, Manifest, ManifestError, SpriteSheet},
+    audio::{
+        Audio, AudioError, AudioOutput, BufferOutput, MixerHandle, PlayOptions, Rolloff, Sound,
+        SoundGroup, Voice,
+    },
+    clipboard::{Clipboard, MemoryClipboard, SystemClipboard},
+    config::{Backend, Config, Fullscreen, PowerPreference, PresentMode},
+    error::RunError,
+    gamepad::{
+        DeadZone, Gamepad, GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent, GamepadId,
+        FakeGamepads, NoGamepads,
+    },
+    input::{Input, Key, KeyEvent, Modifiers, MouseButton, Touch, TouchPhase},
+    queue::{JobCounts, JobPriority, JobProgress, JobQueue, JobToken, LoadError},
     renderer::{Rotate, Renderer},
-    texture::{MakeTransparent, TextureProcessor},
+    replay::{Replay, ReplayError},
+    stats::FrameStats,
+    texture::{MakeTransparent, TextureProcessor, TextureProcessors},
+    vfs::{DirMount, MemoryMount, Mount, PakMount, Vfs, VfsFile},
 };
+#[cfg(feature = "cpal")]
+pub use crate::audio::CpalOutput;
+#[cfg(feature = "gilrs")]
+pub use crate::gamepad::GilrsBackend;
+#[cfg(feature = "zip")]
+pub use crate::vfs::ZipMount;
 
 pub trait Game {
     fn on_start(&mut self, ctx: &mut Ctx);
     fn on_texture_loaded(&mut self, ctx: &mut Ctx, job: JobToken, texture: Texture);
+    fn on_sound_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _sound: Sound) {}
+
+    /// Called once everything from `JobQueue::load_assets` is loaded.
+    fn on_assets_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _assets: Assets) {}
+
+    /// Called when a job started with `JobQueue::spawn` is done. Its result
+    /// has to be taken here with `Ctx::take_job_result`, it is dropped
+    /// afterwards.
+    fn on_job_finished(&mut self, _ctx: &mut Ctx, _job: JobToken) {}
+
+    /// Called when a job reports progress, from `0.0` to `1.0`. Loads only
+    /// report as finely as the format allows: textures jump to `0.8` once
+    /// decoded and to `1.0` once uploaded, WAV and FLAC sounds report while
+    /// decoding, and OGG sounds, whose length isn't known up front, only
+    /// report `1.0`. See `JobProgress` for other jobs.
+    fn on_job_progress(&mut self, _ctx: &mut Ctx, _job: JobToken, _progress: f32) {}
+
+    /// Called instead of the loaded hook when a load fails, e.g. because a
+    /// file is missing or broken. Prints the error by default.
+    fn on_job_failed(&mut self, _ctx: &mut Ctx, job: JobToken, error: LoadError) {
+        eprintln!("job {:?} failed: {}", job, error);
+    }
+
     fn update(&mut self, ctx: &mut Ctx);
     fn draw(&mut self, ctx: &mut Ctx, renderer: &mut Renderer<'_>);
+
+    /// Called after the window was resized, with the new screen size.
+    fn on_resize(&mut self, _ctx: &mut Ctx, _size: (u32, u32)) {}
+
+    /// Called when the window gains or loses keyboard focus. All input is
+    /// released before this is called on focus loss.
+    fn on_focus_changed(&mut self, _ctx: &mut Ctx, _focused: bool) {}
+
+    /// Called when the app is suspended or resumed, on mobile platforms.
+    fn on_suspend_changed(&mut self, _ctx: &mut Ctx, _suspended: bool) {}
+
+    /// Called when files are dragged over or dropped onto the window. See
+    /// `JobQueue::load_dropped_texture` for loading dropped images.
+    fn on_file_drop(&mut self, _ctx: &mut Ctx, _event: FileDrop) {}
+
+    /// Called with the text asked for with `Ctx::clipboard_request_text`.
+    fn on_clipboard_text(&mut self, _ctx: &mut Ctx, _text: io::Result<String>) {}
+
+    /// Called once right before the game shuts down, for whatever reason.
+    fn on_exit(&mut self, _ctx: &mut Ctx) {}
 }
 
 pub struct Ctx {
     job_queue: JobQueue,
     input: Input,
     screen_size: (u32, u32),
+    gamepad_backend: Box<dyn GamepadBackend>,
+    gamepad_events: Vec<GamepadEvent>,
+    actions: ActionMap,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    touch_mouse_emulation: bool,
+    primary_touch: Option<u64>,
+    timer: Timer,
+    frame_history: FrameHistory,
+    stats_overlay: bool,
+    frame_rate_cap: Option<f32>,
+    animating: bool,
+    title: String,
+    fullscreen: Option<Fullscreen>,
+    window_commands: Vec<WindowCommand>,
+    clipboard: Box<dyn Clipboard>,
+    /// Requests to the clipboard that weren't answered yet.
+    clipboard_requests: usize,
+    audio: Audio,
+    /// Result of the job `Game::on_job_finished` is called for.
+    job_result: Option<(JobToken, Box<dyn Any + Send>)>,
 }
 
 impl Ctx {
@@ -51,17 +162,360 @@ impl Ctx {
     pub fn screen_size(&self) -> (u32, u32) {
         self.screen_size
     }
+
+    /// Takes the result of a job started with `JobQueue::spawn`, from
+    /// within `Game::on_job_finished`. Returns `None` for any other job, if
+    /// the result was already taken or if it is not a `T`, in which case it
+    /// is left in place.
+    pub fn take_job_result<T: 'static>(&mut self, job: JobToken) -> Option<T> {
+        match self.job_result.take() {
+            Some((token, result)) if token == job && result.is::<T>() => {
+                result.downcast().ok().map(|result| *result)
+            }
+            other => {
+                self.job_result = other;
+                None
+            }
+        }
+    }
+
+    fn finish_job(&mut self, game: &mut dyn Game, job: JobToken, result: Box<dyn Any + Send>) {
+        self.job_result = Some((job, result));
+        game.on_job_finished(self, job);
+        self.job_result = None;
+    }
+
+    pub fn audio(&mut self) -> &mut Audio {
+        &mut self.audio
+    }
+
+    /// Mount directories and archives here before loading from them.
+    pub fn vfs(&self) -> &Vfs {
+        self.job_queue.vfs()
+    }
+
+    /// Time step of a single update, in seconds.
+    pub fn dt(&self) -> f32 {
+        self.timer.time_per_tick()
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        self.timer.tick_rate()
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.timer.set_tick_rate(tick_rate);
+    }
+
+    pub fn max_backlog(&self) -> f32 {
+        self.timer.max_backlog()
+    }
+
+    pub fn set_max_backlog(&mut self, seconds: f32) {
+        self.timer.set_max_backlog(seconds);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.timer.is_paused()
+    }
+
+    /// Stops running updates. `draw` keeps being called while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.timer.set_paused(paused);
+    }
+
+    /// Runs exactly one update on the next frame while paused.
+    pub fn step(&mut self) {
+        self.timer.step();
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.timer.time_scale()
+    }
+
+    /// Speeds up or slows down the game, e.g. `0.25` for slow motion or
+    /// `4.0` to fast-forward. `dt` stays the same, updates just run more or
+    /// less often.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.timer.set_time_scale(time_scale);
+    }
+
+    /// Fraction of a time step that passed since the last update, for
+    /// interpolating between the previous and the current state in `draw`.
+    /// Always in `0.0..=1.0`.
+    pub fn alpha(&self) -> f32 {
+        self.timer.alpha()
+    }
+
+    /// Number of updates run so far.
+    pub fn ticks(&self) -> u64 {
+        self.timer.ticks()
+    }
+
+    /// Total simulated time, the sum of `dt` over all updates so far.
+    pub fn time(&self) -> f64 {
+        self.timer.time()
+    }
+
+    /// Timings of the last finished frame.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_history.last()
+    }
+
+    /// Timings of recent frames, oldest first.
+    pub fn frame_history(&self) -> impl Iterator<Item = &FrameStats> {
+        self.frame_history.iter()
+    }
+
+    /// Shows a graph of recent frame timings over the game.
+    pub fn set_stats_overlay(&mut self, enabled: bool) {
+        self.stats_overlay = enabled;
+    }
+
+    pub fn frame_rate_cap(&self) -> Option<f32> {
+        self.frame_rate_cap
+    }
+
+    /// Limits how many frames per second are drawn. The event loop sleeps
+    /// between frames instead of spinning.
+    pub fn set_frame_rate_cap(&mut self, fps: Option<f32>) {
+        if let Some(fps) = fps {
+            assert!(fps > 0.0, "frame rate cap must be positive");
+        }
+        self.frame_rate_cap = fps;
+    }
+
+    /// Tells barf whether anything on screen is moving. While not animating
+    /// the event loop sleeps until input arrives or the next update is due,
+    /// instead of drawing as fast as possible. Useful for menus.
+    pub fn set_animating(&mut self, animating: bool) {
+        self.animating = animating;
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animating
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title(&mut self, title: impl Into<String>) {
+        self.title = title.into();
+        self.window_commands.push(WindowCommand::SetTitle(self.title.clone()));
+    }
+
+    pub fn fullscreen(&self) -> Option<Fullscreen> {
+        self.fullscreen
+    }
+
+    /// Switches between windowed (`None`) and fullscreen mode, starting
+    /// with the next frame.
+    pub fn set_fullscreen(&mut self, fullscreen: Option<Fullscreen>) {
+        self.fullscreen = fullscreen;
+        self.window_commands.push(WindowCommand::SetFullscreen(fullscreen));
+    }
+
+    /// Switches between windowed and borderless fullscreen mode.
+    pub fn toggle_fullscreen(&mut self) {
+        match self.fullscreen {
+            Some(_) => self.set_fullscreen(None),
+            None => self.set_fullscreen(Some(Fullscreen::Borderless)),
+        }
+    }
+
+    /// Reads the clipboard without blocking, the text arrives in
+    /// `Game::on_clipboard_text` once it is there.
+    pub fn clipboard_request_text(&mut self) {
+        self.clipboard.request_text();
+        self.clipboard_requests += 1;
+    }
+
+    pub fn clipboard_set_text(&mut self, text: &str) -> io::Result<()> {
+        self.clipboard.set_text(text)
+    }
+
+    /// Requests still pending with the old clipboard are dropped.
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = clipboard;
+        self.clipboard_requests = 0;
+    }
+
+    pub fn actions(&self) -> &ActionMap {
+        &self.actions
+    }
+
+    pub fn actions_mut(&mut self) -> &mut ActionMap {
+        &mut self.actions
+    }
+
+    pub fn is_action_active(&self, action: &str) -> bool {
+        self.actions.is_active(&self.input, action)
+    }
+
+    pub fn action_axis(&self, axis: &str) -> f32 {
+        self.actions.axis(&self.input, axis)
+    }
+
+    pub fn set_gamepad_backend(&mut self, backend: Box<dyn GamepadBackend>) {
+        self.gamepad_backend = backend;
+    }
+
+    pub fn set_gamepad_dead_zone(&mut self, dead_zone: DeadZone) {
+        self.input.gamepads_mut().set_dead_zone(dead_zone);
+    }
+
+    /// Starts writing the input of every following update into a file,
+    /// see `Config::record_input`.
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::create(path, &self.input)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Replaces live input with the recording until it runs out.
+    pub fn start_replay(&mut self, replay: Replay) {
+        self.replay = Some(replay);
+    }
+
+    pub fn stop_replay(&mut self) {
+        if self.replay.take().is_some() {
+            self.input.reset();
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Makes the first finger on the screen act as the left mouse button.
+    pub fn set_touch_mouse_emulation(&mut self, enabled: bool) {
+        self.touch_mouse_emulation = enabled;
+        self.primary_touch = None;
+    }
+
+    fn handle_touch(&mut self, touch: Touch) {
+        self.apply_input(InputEvent::Touch(touch));
+        if !self.touch_mouse_emulation {
+            return;
+        }
+        if touch.phase == TouchPhase::Started && self.primary_touch.is_none() {
+            self.primary_touch = Some(touch.id);
+            self.apply_input(InputEvent::MouseMoved(Some(touch.pos)));
+            self.apply_input(InputEvent::MousePressed(MouseButton::Left));
+        } else if self.primary_touch == Some(touch.id) {
+            self.apply_input(InputEvent::MouseMoved(Some(touch.pos)));
+            if touch.phase == TouchPhase::Ended || touch.phase == TouchPhase::Cancelled {
+                self.primary_touch = None;
+                self.apply_input(InputEvent::MouseReleased(MouseButton::Left));
+            }
+        }
+    }
+
+    fn apply_input(&mut self, event: InputEvent) {
+        if self.replay.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+        self.input.apply(event);
+    }
+
+    fn poll_clipboard(&mut self, game: &mut dyn Game) {
+        while self.clipboard_requests > 0 {
+            match self.clipboard.poll_text() {
+                Some(text) => {
+                    self.clipboard_requests -= 1;
+                    game.on_clipboard_text(self, text);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn poll_gamepads(&mut self) {
+        self.gamepad_backend.poll(&mut self.gamepad_events);
+        let mut events = std::mem::take(&mut self.gamepad_events);
+        for event in events.drain(..) {
+            self.apply_input(InputEvent::Gamepad(event));
+        }
+        self.gamepad_events = events;
+    }
+
+    /// Runs the updates that are due, returns how many ran.
+    fn run_updates(&mut self, game: &mut dyn Game) -> u32 {
+        let mut updates = 0;
+        while self.timer.should_update() {
+            self.begin_update();
+            game.update(self);
+            self.end_update();
+            updates += 1;
+        }
+        updates
+    }
+
+    /// Called after drawing a frame.
+    fn end_frame(&mut self, updates: u32) {
+        // While paused nothing consumes the events, so `draw` would see the
+        // same presses every frame and the first update after resuming all
+        // of them at once. Going through the recorder keeps replays in sync.
+        if updates == 0 && self.timer.is_paused() {
+            self.apply_input(InputEvent::ClearEvents);
+        }
+    }
+
+    fn begin_update(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            if !replay.advance(&mut self.input) {
+                self.stop_replay();
+            }
+        }
+    }
+
+    fn end_update(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.end_tick() {
+                eprintln!("failed to record input: {}", e);
+                self.recorder = None;
+            }
+        }
+        self.input.clear_events();
+    }
+
+    /// Runs when the event loop ends, whether the window was closed or the
+    /// game quit through `JobQueue::quit`.
+    fn exit(&mut self, game: &mut dyn Game) {
+        game.on_exit(self);
+        if let Err(e) = self.stop_recording() {
+            eprintln!("failed to record input: {}", e);
+        }
+    }
 }
 
 struct GameRunner {
     game: Box<dyn Game>,
     wgpu: WgpuState,
     window: Window,
-    timer: Timer,
     ctx: Ctx,
-    job_thread: Option<JoinHandle<()>>,
+    job_workers: Vec<JoinHandle<()>>,
     job_results: Receiver<FinishedJob>,
+    hot_reload: HotReload,
     should_exit: bool,
+    frame: FrameStats,
+    frame_start: Option<Instant>,
+    next_frame: Option<Instant>,
 }
 
 impl GameRunner {
@@ -70,11 +524,15 @@ impl GameRunner {
         event: Event<()>,
         control_flow: &mut ControlFlow,
     ) {
+        // Comes after `should_exit` was set by `JobQueue::quit` too.
+        if let Event::LoopDestroyed = event {
+            self.ctx.exit(&mut *self.game);
+            return;
+        }
         if self.should_exit {
             *control_flow = ControlFlow::Exit;
             return;
         }
-        *control_flow = ControlFlow::Poll;
         match event {
             Event::WindowEvent {
                 event,
@@ -92,74 +550,110 @@ impl GameRunner {
                         virtual_keycode: Some(key),
                         ..
                     } => {
-                        self.ctx.input.press_key(key.into());
+                        self.ctx.apply_input(InputEvent::KeyPressed(key.into()));
                     }
                     KeyboardInput {
                         state: ElementState::Released,
                         virtual_keycode: Some(key),
                         ..
                     } => {
-                        self.ctx.input.release_key(key.into());
+                        self.ctx.apply_input(InputEvent::KeyReleased(key.into()));
                     }
                     _ => {}
                 },
+                WindowEvent::ModifiersChanged(state) => {
+                    self.ctx.apply_input(InputEvent::ModifiersChanged(state.into()));
+                }
                 WindowEvent::MouseInput { state, button, .. } => {
                     match (state, button) {
-                        (ElementState::Pressed, MouseButton::Left) => {
-                            self.ctx.input.set_mouse_left_press(true);
+                        (ElementState::Pressed, event::MouseButton::Left) => {
+                            self.ctx.apply_input(InputEvent::MousePressed(MouseButton::Left));
                         }
-                        (ElementState::Released, MouseButton::Left) => {
-                            self.ctx.input.set_mouse_left_press(false);
+                        (ElementState::Released, event::MouseButton::Left) => {
+                            self.ctx.apply_input(InputEvent::MouseReleased(MouseButton::Left));
                         }
-                        (ElementState::Pressed, MouseButton::Right) => {
-                            self.ctx.input.set_mouse_right_press(true);
+                        (ElementState::Pressed, event::MouseButton::Right) => {
+                            self.ctx.apply_input(InputEvent::MousePressed(MouseButton::Right));
                         }
-                        (ElementState::Released, MouseButton::Right) => {
-                            self.ctx.input.set_mouse_right_press(false);
+                        (ElementState::Released, event::MouseButton::Right) => {
+                            self.ctx.apply_input(InputEvent::MouseReleased(MouseButton::Right));
                         }
-                        (ElementState::Pressed, MouseButton::Middle) |
-                        (ElementState::Pressed, MouseButton::Other(_)) |
-                        (ElementState::Released, MouseButton::Middle) |
-                        (ElementState::Released, MouseButton::Other(_)) => {}
+                        (ElementState::Pressed, event::MouseButton::Middle) |
+                        (ElementState::Pressed, event::MouseButton::Other(_)) |
+                        (ElementState::Released, event::MouseButton::Middle) |
+                        (ElementState::Released, event::MouseButton::Other(_)) => {}
                     }
                 }
                 WindowEvent::MouseWheel { delta, .. } => {
                     match delta {
                         MouseScrollDelta::LineDelta(_, y) if y < 0.0 => {
-                            self.ctx.input.add_mouse_scroll(-1.0);
+                            self.ctx.apply_input(InputEvent::MouseScrolled(-1.0));
                         }
                         MouseScrollDelta::LineDelta(_, y) if y > 0.0 => {
-                            self.ctx.input.add_mouse_scroll(1.0);
+                            self.ctx.apply_input(InputEvent::MouseScrolled(1.0));
                         }
                         MouseScrollDelta::LineDelta(_, _) => {}
                         MouseScrollDelta::PixelDelta(px) if px.y < 0.0 => {
-                            self.ctx.input.add_mouse_scroll(-1.0);
+                            self.ctx.apply_input(InputEvent::MouseScrolled(-1.0));
                         }
                         MouseScrollDelta::PixelDelta(px) if px.y > 0.0 => {
-                            self.ctx.input.add_mouse_scroll(1.0);
+                            self.ctx.apply_input(InputEvent::MouseScrolled(1.0));
                         }
                         MouseScrollDelta::PixelDelta(_) => {}
                     }
                 }
                 WindowEvent::CursorMoved { position, .. } => {
-                    self.ctx.input.set_mouse_pos(Some((position.x as u32, position.y as u32)));
+                    self.ctx.apply_input(InputEvent::MouseMoved(Some((position.x as u32, position.y as u32))));
                 }
                 WindowEvent::CursorLeft { .. } => {
-                    self.ctx.input.set_mouse_pos(None);
+                    self.ctx.apply_input(InputEvent::MouseMoved(None));
+                }
+                WindowEvent::Touch(touch) => {
+                    let phase = match touch.phase {
+                        event::TouchPhase::Started => TouchPhase::Started,
+                        event::TouchPhase::Moved => TouchPhase::Moved,
+                        event::TouchPhase::Ended => TouchPhase::Ended,
+                        event::TouchPhase::Cancelled => TouchPhase::Cancelled,
+                    };
+                    self.ctx.handle_touch(Touch {
+                        id: touch.id,
+                        phase,
+                        pos: (touch.location.x as u32, touch.location.y as u32),
+                    });
+                }
+                WindowEvent::Focused(focused) => {
+                    if !focused {
+                        self.ctx.apply_input(InputEvent::ReleaseAll);
+                        self.ctx.primary_touch = None;
+                    }
+                    self.game.on_focus_changed(&mut self.ctx, focused);
                 }
-                WindowEvent::Focused(false) => {
-                    self.ctx.input.release_all();
+                WindowEvent::HoveredFile(path) => {
+                    self.game.on_file_drop(&mut self.ctx, FileDrop::Hovered(path));
+                }
+                WindowEvent::HoveredFileCancelled => {
+                    self.game.on_file_drop(&mut self.ctx, FileDrop::HoverCancelled);
+                }
+                WindowEvent::DroppedFile(path) => {
+                    self.game.on_file_drop(&mut self.ctx, FileDrop::Dropped(path));
                 }
                 WindowEvent::Resized(physical_size) => {
-                    self.wgpu.resize(physical_size);
+                    self.resize(physical_size);
                 }
                 WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    self.wgpu.resize(*new_inner_size);
+                    self.resize(*new_inner_size);
                 }
                 _ => {}
             },
             Event::RedrawRequested(_) => {
-                match self.wgpu.render() {
+                let render_start = Instant::now();
+                let result = self.wgpu.render();
+                self.frame.render_time = render_start.elapsed();
+                self.frame.instances = self.wgpu.stats.instances;
+                self.frame.batches = self.wgpu.stats.batches;
+                self.frame.gpu_time = self.wgpu.stats.gpu_time;
+                self.ctx.frame_history.push(std::mem::take(&mut self.frame));
+                match result {
                     Ok(_) => {}
                     Err(wgpu::SwapChainError::Lost) => self.wgpu.recreate_swap_chain(),
                     Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
@@ -167,24 +661,91 @@ impl GameRunner {
                 }
             }
             Event::MainEventsCleared => {
+                let now = Instant::now();
+                if let Some(next_frame) = self.next_frame {
+                    if now < next_frame {
+                        *control_flow = ControlFlow::WaitUntil(next_frame);
+                        return;
+                    }
+                }
+                if let Some(start) = self.frame_start.replace(now) {
+                    self.frame.frame_time = now.duration_since(start);
+                }
                 self.process_job_results();
-                let mut updated = false;
-                self.timer.tick();
+                self.hot_reload.poll(&self.ctx.job_queue);
+                self.ctx.poll_clipboard(&mut *self.game);
+                self.ctx.poll_gamepads();
+                let dropped = self.ctx.timer.tick();
+                self.frame.dropped_time = Duration::from_secs_f32(dropped);
                 self.ctx.screen_size = (self.wgpu.size.width, self.wgpu.size.height);
-                while self.timer.should_update() {
-                    self.game.update(&mut self.ctx);
-                    updated = true;
-                }
-                if updated {
-                    let mut renderer = Renderer::new(&mut self.wgpu);
-                    self.game.draw(&mut self.ctx, &mut renderer);
+                let update_start = Instant::now();
+                let updates = self.ctx.run_updates(&mut *self.game);
+                self.frame.updates += updates;
+                self.frame.update_time = update_start.elapsed();
+                let draw_start = Instant::now();
+                let mut renderer = Renderer::new(&mut self.wgpu);
+                self.game.draw(&mut self.ctx, &mut renderer);
+                self.ctx.audio.set_listener(renderer.view());
+                self.frame.draw_time = draw_start.elapsed();
+                if self.ctx.stats_overlay {
+                    let budget = self.ctx.timer.time_per_tick();
+                    stats::draw_overlay(&self.ctx.frame_history, budget, &mut renderer);
                 }
+                self.ctx.end_frame(updates);
+                self.apply_window_commands();
                 self.window.request_redraw();
+                *control_flow = self.frame_pacing(now);
             }
+            Event::Suspended => self.game.on_suspend_changed(&mut self.ctx, true),
+            Event::Resumed => self.game.on_suspend_changed(&mut self.ctx, false),
             _ => {}
         }
     }
 
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.wgpu.resize(size);
+        let size = (self.wgpu.size.width, self.wgpu.size.height);
+        self.ctx.screen_size = size;
+        self.game.on_resize(&mut self.ctx, size);
+    }
+
+    fn frame_pacing(&mut self, frame_start: Instant) -> ControlFlow {
+        self.next_frame = self.ctx.frame_rate_cap
+            .map(|fps| frame_start + Duration::from_secs_f32(1.0 / fps));
+        let wake_up = if self.ctx.animating {
+            self.next_frame
+        } else {
+            let now = Instant::now();
+            let next_update = self.ctx.timer.until_next_update().map(|delay| now + delay);
+            let next_clipboard_poll = if self.ctx.clipboard_requests > 0 {
+                Some(now + clipboard::POLL_INTERVAL)
+            } else {
+                None
+            };
+            let wake_ups = [next_update, self.hot_reload.next_poll(), next_clipboard_poll];
+            let next = match wake_ups.iter().flatten().min() {
+                Some(&next) => next,
+                None => return ControlFlow::Wait,
+            };
+            Some(self.next_frame.map_or(next, |frame| frame.max(next)))
+        };
+        match wake_up {
+            Some(time) => ControlFlow::WaitUntil(time),
+            None => ControlFlow::Poll,
+        }
+    }
+
+    fn apply_window_commands(&mut self) {
+        for command in self.ctx.window_commands.drain(..) {
+            match command {
+                WindowCommand::SetTitle(title) => self.window.set_title(&title),
+                WindowCommand::SetFullscreen(fullscreen) => {
+                    window::set_fullscreen(&self.window, fullscreen);
+                }
+            }
+        }
+    }
+
     fn process_job_results(&mut self) {
         while !self.should_exit {
             match self.job_results.try_recv() {
@@ -197,13 +758,45 @@ impl GameRunner {
 
     fn process_job_result(&mut self, result: FinishedJob) {
         match result {
-            FinishedJob::TextureLoaded { token, texture } => {
+            FinishedJob::TextureLoaded { token, texture, source } => {
                 self.wgpu.textures.push(texture);
                 let index = Texture { index: self.wgpu.textures.len() - 1 };
+                if let Some(source) = source {
+                    self.hot_reload.watch(index, source);
+                }
                 self.game.on_texture_loaded(&mut self.ctx, token, index);
             }
+            FinishedJob::TextureReloaded { texture, reloaded } => {
+                if let Some(reloaded) = reloaded {
+                    self.wgpu.textures[texture.index] = reloaded;
+                }
+                self.hot_reload.reloaded(texture);
+            }
+            FinishedJob::SoundLoaded { token, sound } => {
+                let sound = self.ctx.audio.add_sound(sound);
+                self.game.on_sound_loaded(&mut self.ctx, token, sound);
+            }
+            FinishedJob::AssetsLoaded { token, assets } => {
+                let assets = assets.register(&mut self.wgpu.textures, &mut self.ctx.audio, &mut self.hot_reload);
+                self.game.on_assets_loaded(&mut self.ctx, token, assets);
+            }
+            FinishedJob::Finished { token, result } => {
+                self.ctx.finish_job(&mut *self.game, token, result);
+            }
+            FinishedJob::Failed { token, error } => {
+                self.game.on_job_failed(&mut self.ctx, token, error);
+            }
+            FinishedJob::Progress { token, progress } => {
+                if !self.ctx.job_queue.is_cancelled(token) {
+                    self.game.on_job_progress(&mut self.ctx, token, progress);
+                }
+            }
+            FinishedJob::Cancelled(token) => self.ctx.job_queue.cancel_done(token),
+            FinishedJob::WorkerDied => panic!("job worker died"),
             FinishedJob::Quit => {
-                self.job_thread.take().unwrap().join().unwrap();
+                for worker in self.job_workers.drain(..) {
+                    worker.join().unwrap();
+                }
                 self.should_exit = true;
             }
         }
@@ -211,29 +804,88 @@ impl GameRunner {
 }
 
 pub fn run(game: Box<dyn Game>) {
+    run_with_config(game, Config::default())
+}
+
+/// Like `try_run`, but prints the error and exits the process if the game
+/// fails to start.
+pub fn run_with_config(game: Box<dyn Game>, config: Config) {
+    if let Err(e) = try_run(game, config) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Starts the game. This only returns if starting fails, once the event loop
+/// is running the process exits together with it.
+pub fn try_run(game: Box<dyn Game>, config: Config) -> Result<(), RunError> {
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let wgpu = futures::executor::block_on(WgpuState::new(&window));
-    let (job_queue, job_thread, job_results) = queue::start_job_thread(
+    let window = window::build(&config, &event_loop)?;
+    let wgpu = futures::executor::block_on(WgpuState::new(&window, &config))?;
+    let proxy = Mutex::new(event_loop.create_proxy());
+    let vfs = Vfs::new();
+    let (job_queue, job_workers, job_results) = queue::start_job_workers(
+        config.job_workers,
         wgpu.device.clone(),
         wgpu.queue.clone(),
         wgpu.texture_bind_group_layout.clone(),
+        Arc::new(move || { proxy.lock().unwrap().send_event(()).ok(); }),
+        vfs.clone(),
+        config.hot_reload,
     );
     let screen_size = window.inner_size();
     let mut runner = GameRunner {
         game,
         wgpu,
         window,
-        timer: Timer::new(),
-        job_thread: Some(job_thread),
+        job_workers,
         job_results,
+        hot_reload: HotReload::new(config.hot_reload),
         ctx: Ctx {
             job_queue,
             input: Input::default(),
             screen_size: (screen_size.width, screen_size.height),
+            gamepad_backend: gamepad::default_backend(),
+            gamepad_events: Vec::new(),
+            actions: ActionMap::new(),
+            recorder: None,
+            replay: None,
+            touch_mouse_emulation: false,
+            primary_touch: None,
+            timer: Timer::new(config.tick_rate, config.max_backlog),
+            frame_history: FrameHistory::default(),
+            stats_overlay: false,
+            frame_rate_cap: config.frame_rate_cap,
+            animating: true,
+            title: config.title.clone(),
+            fullscreen: config.fullscreen,
+            window_commands: Vec::new(),
+            clipboard: clipboard::default_clipboard(),
+            clipboard_requests: 0,
+            audio: Audio::new(audio::default_output(), vfs),
+            job_result: None,
         },
         should_exit: false,
+        frame: FrameStats::default(),
+        frame_start: None,
+        next_frame: None,
     };
+    // Matches the default view of the renderer until the first frame is drawn.
+    runner.ctx.audio.set_listener(Rect {
+        x: 0.0,
+        y: 0.0,
+        w: screen_size.width as f32,
+        h: screen_size.height as f32,
+    });
+    if let Some(path) = &config.replay_input {
+        let replay = Replay::load(path)
+            .map_err(|e| RunError::LoadReplay(path.clone(), e))?;
+        runner.ctx.start_replay(replay);
+    }
+    if let Some(path) = &config.record_input {
+        runner.ctx.start_recording(path)
+            .map_err(|e| RunError::CreateRecording(path.clone(), e))?;
+    }
     runner.game.on_start(&mut runner.ctx);
 
     event_loop.run(move |event, _, control_flow| runner.event(event, control_flow));
@@ -244,6 +896,16 @@ pub struct Texture {
     index: usize,
 }
 
+/// A file being dragged onto the window. Dragging several files at once
+/// sends one event per file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDrop {
+    Hovered(PathBuf),
+    /// The files were dragged away again without dropping them.
+    HoverCancelled,
+    Dropped(PathBuf),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Rect {
     pub x: f32,
@@ -251,3 +913,214 @@ pub struct Rect {
     pub w: f32,
     pub h: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context without window, workers or devices.
+    pub(crate) fn test_ctx() -> Ctx {
+        let vfs = Vfs::new();
+        let config = Config::default();
+        Ctx {
+            job_queue: queue::detached_queue(vfs.clone()),
+            input: Input::default(),
+            screen_size: (640, 480),
+            gamepad_backend: Box::new(NoGamepads),
+            gamepad_events: Vec::new(),
+            actions: ActionMap::new(),
+            recorder: None,
+            replay: None,
+            touch_mouse_emulation: false,
+            primary_touch: None,
+            timer: Timer::new(config.tick_rate, config.max_backlog),
+            frame_history: FrameHistory::default(),
+            stats_overlay: false,
+            frame_rate_cap: config.frame_rate_cap,
+            animating: true,
+            title: config.title.clone(),
+            fullscreen: config.fullscreen,
+            window_commands: Vec::new(),
+            clipboard: Box::new(MemoryClipboard::new()),
+            clipboard_requests: 0,
+            audio: Audio::new(None, vfs),
+            job_result: None,
+        }
+    }
+
+    /// Remembers the clipboard texts it got.
+    #[derive(Default)]
+    struct PasteGame {
+        texts: Vec<String>,
+    }
+
+    impl Game for PasteGame {
+        fn on_start(&mut self, _ctx: &mut Ctx) {}
+        fn on_texture_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _texture: Texture) {}
+        fn update(&mut self, _ctx: &mut Ctx) {}
+        fn draw(&mut self, _ctx: &mut Ctx, _renderer: &mut Renderer<'_>) {}
+
+        fn on_clipboard_text(&mut self, _ctx: &mut Ctx, text: io::Result<String>) {
+            self.texts.push(text.unwrap());
+        }
+    }
+
+    #[test]
+    fn clipboard_texts_reach_the_game() {
+        let mut ctx = test_ctx();
+        let mut game = PasteGame::default();
+        ctx.clipboard_set_text("hello").unwrap();
+        ctx.clipboard_request_text();
+        ctx.clipboard_set_text("again").unwrap();
+        ctx.clipboard_request_text();
+        ctx.poll_clipboard(&mut game);
+        assert_eq!(game.texts, ["hello", "again"]);
+        assert_eq!(ctx.clipboard_requests, 0);
+
+        // requests to a replaced clipboard are dropped
+        ctx.clipboard_request_text();
+        ctx.set_clipboard(Box::new(MemoryClipboard::new()));
+        ctx.poll_clipboard(&mut game);
+        assert_eq!(game.texts.len(), 2);
+    }
+
+    struct JobGame {
+        taken: Option<Option<String>>,
+    }
+
+    impl Game for JobGame {
+        fn on_start(&mut self, _ctx: &mut Ctx) {}
+        fn on_texture_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _texture: Texture) {}
+        fn update(&mut self, _ctx: &mut Ctx) {}
+        fn draw(&mut self, _ctx: &mut Ctx, _renderer: &mut Renderer<'_>) {}
+
+        fn on_job_finished(&mut self, ctx: &mut Ctx, job: JobToken) {
+            assert_eq!(ctx.take_job_result::<u32>(job), None);
+            let other = ctx.job_queue().spawn(|| "other".to_owned());
+            assert_eq!(ctx.take_job_result::<String>(other), None);
+            if let Some(taken) = &mut self.taken {
+                *taken = ctx.take_job_result::<String>(job);
+                assert_eq!(ctx.take_job_result::<String>(job), None);
+            }
+        }
+    }
+
+    #[test]
+    fn job_results() {
+        let mut ctx = test_ctx();
+        let job = ctx.job_queue().spawn(|| "done".to_owned());
+        let mut game = JobGame { taken: Some(None) };
+        ctx.finish_job(&mut game, job, Box::new("done".to_owned()));
+        assert_eq!(game.taken, Some(Some("done".to_owned())));
+
+        // untaken results are dropped
+        let job = ctx.job_queue().spawn(|| "ignored".to_owned());
+        let mut game = JobGame { taken: None };
+        ctx.finish_job(&mut game, job, Box::new("ignored".to_owned()));
+        assert_eq!(ctx.take_job_result::<String>(job), None);
+        assert!(ctx.job_result.is_none());
+    }
+
+    #[derive(Default)]
+    struct ExitGame {
+        exited: bool,
+    }
+
+    impl Game for ExitGame {
+        fn on_start(&mut self, _ctx: &mut Ctx) {}
+        fn on_texture_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _texture: Texture) {}
+        fn update(&mut self, _ctx: &mut Ctx) {}
+        fn draw(&mut self, _ctx: &mut Ctx, _renderer: &mut Renderer<'_>) {}
+
+        fn on_exit(&mut self, ctx: &mut Ctx) {
+            assert!(ctx.is_recording(), "recording stops after on_exit");
+            self.exited = true;
+        }
+    }
+
+    #[test]
+    fn recording_is_finished_after_quitting_through_the_job_queue() {
+        let path = std::env::temp_dir().join(format!("barf-quit-recording-{}", std::process::id()));
+        let mut ctx = test_ctx();
+        ctx.start_recording(&path).unwrap();
+        for key in &[Key::A, Key::B, Key::C] {
+            ctx.begin_update();
+            ctx.apply_input(InputEvent::KeyPressed(*key));
+            ctx.end_update();
+        }
+        ctx.job_queue().quit();
+
+        let mut game = ExitGame::default();
+        ctx.exit(&mut game);
+        assert!(game.exited);
+        assert!(!ctx.is_recording());
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.unwrap().len(), 3);
+    }
+
+    /// Remembers the key events every update saw.
+    #[derive(Default)]
+    struct KeyGame {
+        updates: Vec<Vec<KeyEvent>>,
+    }
+
+    impl Game for KeyGame {
+        fn on_start(&mut self, _ctx: &mut Ctx) {}
+        fn on_texture_loaded(&mut self, _ctx: &mut Ctx, _job: JobToken, _texture: Texture) {}
+        fn draw(&mut self, _ctx: &mut Ctx, _renderer: &mut Renderer<'_>) {}
+
+        fn update(&mut self, ctx: &mut Ctx) {
+            self.updates.push(ctx.input().key_events().to_vec());
+        }
+    }
+
+    #[test]
+    fn events_do_not_pile_up_while_paused() {
+        let path = std::env::temp_dir().join(format!("barf-paused-recording-{}", std::process::id()));
+        let mut ctx = test_ctx();
+        let mut game = KeyGame::default();
+        ctx.set_tick_rate(1000.0);
+        ctx.start_recording(&path).unwrap();
+        ctx.set_paused(true);
+
+        ctx.apply_input(InputEvent::KeyPressed(Key::A));
+        ctx.timer.tick();
+        assert_eq!(ctx.run_updates(&mut game), 0);
+        assert!(ctx.input().was_pressed(Key::A), "draw sees the press");
+        ctx.end_frame(0);
+
+        ctx.timer.tick();
+        assert_eq!(ctx.run_updates(&mut game), 0);
+        assert!(!ctx.input().was_pressed(Key::A), "but only in one frame");
+        assert!(ctx.input().is_pressed(Key::A));
+        ctx.end_frame(0);
+
+        ctx.set_paused(false);
+        ctx.apply_input(InputEvent::KeyPressed(Key::B));
+        std::thread::sleep(Duration::from_millis(5));
+        ctx.timer.tick();
+        let updates = ctx.run_updates(&mut game);
+        assert!(updates > 0);
+        ctx.end_frame(updates);
+        assert_eq!(game.updates[0], [KeyEvent::Pressed(Key::B)]);
+        assert!(game.updates[1..].iter().all(|events| events.is_empty()));
+        ctx.stop_recording().unwrap();
+
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let replay = replay.unwrap();
+        assert_eq!(replay.len(), game.updates.len());
+        let mut ctx = test_ctx();
+        let mut replayed = KeyGame::default();
+        ctx.start_replay(replay);
+        while ctx.is_replaying() {
+            ctx.begin_update();
+            if ctx.is_replaying() {
+                replayed.update(&mut ctx);
+            }
+            ctx.end_update();
+        }
+        assert_eq!(replayed.updates, game.updates);
+    }
+}
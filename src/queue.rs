@@ -1,31 +1,81 @@
 use std::{
+    any::Any,
+    borrow::Cow,
+    cell::Cell,
+    cmp,
+    collections::{BinaryHeap, HashSet},
+    error::Error,
+    fmt, io,
     path::{Path, PathBuf},
     sync::{
-        Arc,
-        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         mpsc::{Receiver, Sender, channel},
     },
     thread::JoinHandle,
 };
 use crate::{
-    TextureProcessor,
+    AudioError, Manifest, TextureProcessors, Vfs,
+    assets::{Asset, LoadedAsset, LoadedAssets},
+    decode::{self, SoundData},
+    hot_reload::TextureSource,
     texture::{BindableTexture, Texture},
 };
 
+pub(crate) const DEFAULT_JOB_WORKERS: usize = 2;
+
+/// Jobs with a higher priority are started first, jobs with the same
+/// priority in the order they were submitted.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum JobPriority {
+    Background,
+    Normal,
+    High,
+}
+
+/// Snapshot of the jobs in a `JobQueue`. Internal jobs such as hot reloads
+/// are not counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JobCounts {
+    /// Jobs waiting for a worker.
+    pub pending: usize,
+    /// Jobs a worker is busy with.
+    pub running: usize,
+    /// Jobs finished since the game started, not counting cancelled ones.
+    pub completed: u64,
+}
+
 #[derive(Clone)]
 pub struct JobQueue {
     inner: Arc<JobQueueInner>,
+    priority: JobPriority,
 }
 
 impl JobQueue {
+    /// Returns a queue that submits jobs with the given priority. The
+    /// default is `JobPriority::Normal`.
+    pub fn with_priority(&self, priority: JobPriority) -> JobQueue {
+        JobQueue {
+            inner: self.inner.clone(),
+            priority,
+        }
+    }
+
+    pub fn priority(&self) -> JobPriority {
+        self.priority
+    }
+
+    /// Where all loads read their files from.
+    pub fn vfs(&self) -> &Vfs {
+        &self.inner.vfs
+    }
+
     pub fn load_texture_from_file(
         &self,
         path: PathBuf,
-        processors: &'static [&'static dyn TextureProcessor],
+        processors: TextureProcessors,
     ) -> JobToken {
-        let token = self.inner.generate_token();
-        self.send(JobRequest::LoadTextureFromFile(token, path, processors));
-        token
+        self.send(JobRequest::LoadTextureFromFile(path, processors))
     }
 
     pub fn load_texture_from_rgba(
@@ -33,26 +83,151 @@ impl JobQueue {
         rgba: Vec<u8>,
         width: u32,
         height: u32,
-        processors: &'static [&'static dyn TextureProcessor],
+        processors: TextureProcessors,
     ) -> JobToken {
         assert_eq!((width * height * 4) as usize, rgba.len());
-        let token = self.inner.generate_token();
-        self.send(JobRequest::LoadTextureFromRgba(token, rgba, width, height, processors));
-        token
+        self.send(JobRequest::LoadTextureFromRgba(rgba, width, height, processors))
+    }
+
+    /// Decodes an image file that is already in memory, e.g. one embedded
+    /// with `include_bytes!` (pass it as `&BYTES[..]`) or read from an
+    /// archive. Any format supported by `image` works, anything else ends up
+    /// in `Game::on_job_failed`.
+    pub fn load_texture_from_bytes(
+        &self,
+        bytes: impl Into<Cow<'static, [u8]>>,
+        processors: TextureProcessors,
+    ) -> JobToken {
+        self.send(JobRequest::LoadTextureFromBytes(bytes.into(), processors))
+    }
+
+    /// Decodes a WAV, OGG or FLAC file. OGG and FLAC need the `lewton` and
+    /// `claxon` features. Missing or broken files end up in
+    /// `Game::on_job_failed`.
+    pub fn load_sound_from_file(&self, path: PathBuf) -> JobToken {
+        self.send(JobRequest::LoadSoundFromFile(path))
     }
 
+    /// Loads everything in `manifest` as one job. Once all of it is loaded
+    /// `Game::on_assets_loaded` is called with the handles by name. If any
+    /// of it fails to load, `Game::on_job_failed` is called instead.
+    pub fn load_assets(&self, manifest: Manifest) -> JobToken {
+        self.send(JobRequest::LoadAssets(manifest))
+    }
+
+    /// Runs `job` on a worker thread. Once it is done `Game::on_job_finished`
+    /// is called, where the result can be taken with `Ctx::take_job_result`.
+    pub fn spawn<T, F>(&self, job: F) -> JobToken
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.spawn_with_progress(|_| job())
+    }
+
+    /// Like `spawn`, but the job can report progress and check whether it
+    /// was cancelled.
+    pub fn spawn_with_progress<T, F>(&self, job: F) -> JobToken
+    where
+        T: Send + 'static,
+        F: FnOnce(&JobProgress<'_>) -> T + Send + 'static,
+    {
+        self.send(JobRequest::Run(Box::new(move |progress| Box::new(job(progress)))))
+    }
+
+    /// Loads a file dropped onto the window as a texture, if it looks like
+    /// an image. Returns `None` for other files.
+    pub fn load_dropped_texture(&self, path: &Path) -> Option<JobToken> {
+        image::ImageFormat::from_path(path).ok()?;
+        Some(self.load_texture_from_file(path.to_owned(), TextureProcessors::new()))
+    }
+
+    /// Drops a job that didn't start yet, or asks a running one to stop.
+    /// The game isn't told about cancelled jobs anymore, not even their
+    /// progress. Returns false if the job already finished.
+    pub fn cancel(&self, token: JobToken) -> bool {
+        let mut state = self.inner.state.lock().unwrap();
+        let pending = state.jobs.len();
+        let jobs = std::mem::take(&mut state.jobs).into_vec();
+        state.jobs = jobs.into_iter().filter(|job| job.token != token).collect();
+        if state.jobs.len() < pending {
+            return true;
+        }
+        if state.running.contains(&token) {
+            state.cancelled.insert(token);
+            return true;
+        }
+        false
+    }
+
+    pub fn counts(&self) -> JobCounts {
+        let state = self.inner.state.lock().unwrap();
+        JobCounts {
+            pending: state.jobs.iter().filter(|job| job.request.is_counted()).count(),
+            running: state.running.len(),
+            completed: state.completed,
+        }
+    }
+
+    /// Exits the game once all jobs submitted so far are done.
     pub fn quit(&self) {
-        self.send(JobRequest::Quit);
+        self.inner.state.lock().unwrap().quitting = true;
+        self.inner.available.notify_all();
     }
 
-    fn send(&self, request: JobRequest) {
-        self.inner.sender.send(request).ok();
+    /// Loads the file of an existing texture again, see `Config::hot_reload`.
+    /// Returns false if the queue is quitting and the reload was dropped.
+    pub(crate) fn reload_texture(
+        &self,
+        texture: crate::Texture,
+        path: PathBuf,
+        processors: TextureProcessors,
+    ) -> bool {
+        let token = self.inner.generate_token();
+        self.enqueue(token, JobRequest::ReloadTexture(texture, path, processors))
+    }
+
+    /// Whether the job was cancelled and the worker didn't acknowledge it yet.
+    pub(crate) fn is_cancelled(&self, token: JobToken) -> bool {
+        self.inner.state.lock().unwrap().cancelled.contains(&token)
+    }
+
+    /// Forgets a cancelled job once its worker is done with it.
+    pub(crate) fn cancel_done(&self, token: JobToken) {
+        self.inner.state.lock().unwrap().cancelled.remove(&token);
+    }
+
+    fn send(&self, request: JobRequest) -> JobToken {
+        let token = self.inner.generate_token();
+        self.enqueue(token, request);
+        token
+    }
+
+    /// Returns false if the queue is quitting and the job was dropped.
+    fn enqueue(&self, token: JobToken, request: JobRequest) -> bool {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.quitting {
+            return false;
+        }
+        let order = state.next_order;
+        state.next_order += 1;
+        state.jobs.push(QueuedJob {
+            priority: self.priority,
+            order,
+            token,
+            request,
+        });
+        drop(state);
+        self.inner.available.notify_one();
+        true
     }
 }
 
 struct JobQueueInner {
-    sender: Sender<JobRequest>,
+    state: Mutex<QueueState>,
+    available: Condvar,
     token_generator: AtomicU64,
+    vfs: Vfs,
 }
 
 impl JobQueueInner {
@@ -60,96 +235,552 @@ impl JobQueueInner {
         let token = self.token_generator.fetch_add(1, Ordering::Relaxed);
         JobToken(token)
     }
+
+    /// Blocks until there is a job to run, `None` once the queue is quitting
+    /// and empty.
+    fn next_job(&self) -> Option<(JobToken, JobRequest)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop() {
+                if job.request.is_counted() {
+                    state.running.insert(job.token);
+                }
+                return Some((job.token, job.request));
+            }
+            if state.quitting {
+                return None;
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: BinaryHeap<QueuedJob>,
+    next_order: u64,
+    running: HashSet<JobToken>,
+    /// Running jobs that were cancelled, until the main thread got the
+    /// `FinishedJob::Cancelled` for them.
+    cancelled: HashSet<JobToken>,
+    completed: u64,
+    quitting: bool,
+}
+
+struct QueuedJob {
+    priority: JobPriority,
+    order: u64,
+    token: JobToken,
+    request: JobRequest,
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &QueuedJob) -> cmp::Ordering {
+        self.priority.cmp(&other.priority).then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &QueuedJob) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &QueuedJob) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub struct JobToken(u64);
 
+/// Why a load job failed, see `Game::on_job_failed`.
+#[derive(Debug)]
+pub enum LoadError {
+    Image(PathBuf, image::ImageError),
+    /// Bytes given to `JobQueue::load_texture_from_bytes` aren't an image.
+    ImageBytes(image::ImageError),
+    Sound(PathBuf, AudioError),
+    Data(PathBuf, io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Image(path, e) => write!(f, "failed to load {}: {}", path.display(), e),
+            LoadError::ImageBytes(e) => write!(f, "failed to load texture from memory: {}", e),
+            LoadError::Sound(path, e) => write!(f, "failed to load {}: {}", path.display(), e),
+            LoadError::Data(path, e) => write!(f, "failed to load {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Image(_, e) | LoadError::ImageBytes(e) => Some(e),
+            LoadError::Sound(_, e) => Some(e),
+            LoadError::Data(_, e) => Some(e),
+        }
+    }
+}
+
+/// Handed to running jobs to report progress and check for cancellation.
+pub struct JobProgress<'a> {
+    runner: &'a JobRunner,
+    token: JobToken,
+    reported: Cell<f32>,
+    /// Part of the overall progress this covers, for jobs made of steps.
+    range: (f32, f32),
+}
+
+/// Progress events closer together than this are skipped.
+const PROGRESS_STEP: f32 = 0.01;
+
+impl JobProgress<'_> {
+    pub fn token(&self) -> JobToken {
+        self.token
+    }
+
+    /// Reports how much of the job is done, from `0.0` to `1.0`. Arrives at
+    /// the game as `Game::on_job_progress`. Non-finite values are ignored.
+    pub fn set(&self, progress: f32) {
+        if !progress.is_finite() {
+            return;
+        }
+        let (start, end) = self.range;
+        let progress = start + progress.clamp(0.0, 1.0) * (end - start);
+        if progress < self.reported.get() + PROGRESS_STEP && progress < 1.0 {
+            return;
+        }
+        self.reported.set(progress);
+        self.runner.send(FinishedJob::Progress { token: self.token, progress });
+    }
+
+    /// Whether the job was cancelled with `JobQueue::cancel`. Long jobs
+    /// should check this every now and then and return early.
+    pub fn is_cancelled(&self) -> bool {
+        self.runner.inner.state.lock().unwrap().cancelled.contains(&self.token)
+    }
+}
+
+impl<'a> JobProgress<'a> {
+    /// Progress for a step of the job that makes up `start..end` of it.
+    fn part(&self, start: f32, end: f32) -> JobProgress<'a> {
+        let (from, to) = self.range;
+        let start = from + start * (to - from);
+        JobProgress {
+            runner: self.runner,
+            token: self.token,
+            reported: Cell::new(start),
+            range: (start, from + end * (to - from)),
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce(&JobProgress<'_>) -> Box<dyn Any + Send> + Send>;
+
 enum JobRequest {
-    Quit,
-    LoadTextureFromFile(JobToken, PathBuf, &'static [&'static dyn TextureProcessor]),
-    LoadTextureFromRgba(JobToken, Vec<u8>, u32, u32, &'static [&'static dyn TextureProcessor]),
+    LoadTextureFromFile(PathBuf, TextureProcessors),
+    LoadTextureFromRgba(Vec<u8>, u32, u32, TextureProcessors),
+    LoadTextureFromBytes(Cow<'static, [u8]>, TextureProcessors),
+    LoadSoundFromFile(PathBuf),
+    LoadAssets(Manifest),
+    ReloadTexture(crate::Texture, PathBuf, TextureProcessors),
+    Run(Job),
+}
+
+impl JobRequest {
+    /// Whether the job shows up in `JobCounts`, i.e. wasn't started by the
+    /// engine itself.
+    fn is_counted(&self) -> bool {
+        !matches!(self, JobRequest::ReloadTexture(..))
+    }
 }
 
 pub(crate) enum FinishedJob {
     TextureLoaded {
         token: JobToken,
         texture: BindableTexture,
+        source: Option<TextureSource>,
+    },
+    /// `None` if the file couldn't be loaded, the old texture stays then.
+    TextureReloaded {
+        texture: crate::Texture,
+        reloaded: Option<BindableTexture>,
+    },
+    SoundLoaded {
+        token: JobToken,
+        sound: SoundData,
+    },
+    AssetsLoaded {
+        token: JobToken,
+        assets: LoadedAssets,
+    },
+    Finished {
+        token: JobToken,
+        result: Box<dyn Any + Send>,
     },
+    Failed {
+        token: JobToken,
+        error: LoadError,
+    },
+    Progress {
+        token: JobToken,
+        progress: f32,
+    },
+    /// A cancelled job stopped running.
+    Cancelled(JobToken),
+    /// A job panicked and took its worker down with it.
+    WorkerDied,
     Quit,
 }
 
-pub(crate) fn start_job_thread(
+/// Starts `workers` threads that share one queue. Jobs upload to the GPU
+/// right from the worker through the shared device and queue. The uploads
+/// only take effect with the next submit, which the main thread does after
+/// receiving the texture, so it never sees a texture before its data.
+pub(crate) fn start_job_workers(
+    workers: usize,
     gpu_device: Arc<wgpu::Device>,
     gpu_queue: Arc<wgpu::Queue>,
     texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
-) -> (JobQueue, JoinHandle<()>, Receiver<FinishedJob>) {
-    let (job_tx, job_rx) = channel();
+    wake_up: Arc<dyn Fn() + Send + Sync>,
+    vfs: Vfs,
+    hot_reload: bool,
+) -> (JobQueue, Vec<JoinHandle<()>>, Receiver<FinishedJob>) {
+    assert!(workers > 0, "need at least one job worker");
     let (result_tx, result_rx) = channel();
-    let mut runner = JobRunner {
+    let inner = Arc::new(JobQueueInner {
+        state: Mutex::new(QueueState::default()),
+        available: Condvar::new(),
+        token_generator: AtomicU64::new(0),
+        vfs,
+    });
+    let runner = JobRunner {
+        inner: inner.clone(),
         result_sender: result_tx,
         gpu_device,
         gpu_queue,
         texture_bind_group_layout,
+        wake_up,
+        hot_reload,
     };
-    let handle = std::thread::spawn(move || {
-        for job in job_rx {
-            match job {
-                JobRequest::Quit => {
-                    runner.quit();
-                    break;
-                }
-                JobRequest::LoadTextureFromFile(token, path, processors) => {
-                    runner.load_texture_from_file(token, &path, processors);
-                }
-                JobRequest::LoadTextureFromRgba(token, rgba, width, height, processors) => {
-                    runner.load_texture_from_rgba(token, rgba, width, height, processors);
-                }
+    let running = Arc::new(AtomicUsize::new(workers));
+    let handles = (0..workers).map(|_| {
+        let runner = runner.clone();
+        let running = running.clone();
+        std::thread::spawn(move || {
+            while let Some((token, job)) = runner.inner.next_job() {
+                runner.run(token, job);
             }
-        }
-    });
+            if running.fetch_sub(1, Ordering::AcqRel) == 1 {
+                runner.quit();
+            }
+        })
+    }).collect();
     let queue = JobQueue {
+        inner,
+        priority: JobPriority::Normal,
+    };
+    (queue, handles, result_rx)
+}
+
+/// A queue without workers, jobs sent to it are never run.
+#[cfg(test)]
+pub(crate) fn detached_queue(vfs: Vfs) -> JobQueue {
+    JobQueue {
         inner: Arc::new(JobQueueInner {
-            sender: job_tx,
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
             token_generator: AtomicU64::new(0),
+            vfs,
         }),
-    };
-    (queue, handle, result_rx)
+        priority: JobPriority::Normal,
+    }
 }
 
+#[derive(Clone)]
 struct JobRunner {
+    inner: Arc<JobQueueInner>,
     result_sender: Sender<FinishedJob>,
     gpu_device: Arc<wgpu::Device>,
     gpu_queue: Arc<wgpu::Queue>,
     texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Wakes up the event loop in case it is sleeping.
+    wake_up: Arc<dyn Fn() + Send + Sync>,
+    /// Whether loaded textures remember their source, see `Config::hot_reload`.
+    hot_reload: bool,
+}
+
+impl Drop for JobRunner {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.send(FinishedJob::WorkerDied);
+        }
+    }
 }
 
 impl JobRunner {
-    fn quit(self) {
-        self.result_sender.send(FinishedJob::Quit).unwrap();
+    fn quit(&self) {
+        self.send(FinishedJob::Quit);
+    }
+
+    fn send(&self, job: FinishedJob) {
+        self.result_sender.send(job).ok();
+        (self.wake_up)();
     }
 
+    fn run(&self, token: JobToken, job: JobRequest) {
+        let progress = JobProgress {
+            runner: self,
+            token,
+            reported: Cell::new(0.0),
+            range: (0.0, 1.0),
+        };
+        let counted = job.is_counted();
+        let result = match job {
+            JobRequest::LoadTextureFromFile(path, processors) => {
+                self.load_texture_from_file(&progress, &path, &processors)
+            }
+            JobRequest::LoadTextureFromRgba(rgba, width, height, processors) => {
+                Some(self.load_texture_from_rgba(token, rgba, width, height, &processors))
+            }
+            JobRequest::LoadTextureFromBytes(bytes, processors) => {
+                Some(self.load_texture_from_bytes(token, &bytes, &processors))
+            }
+            JobRequest::LoadSoundFromFile(path) => self.load_sound_from_file(&progress, &path),
+            JobRequest::LoadAssets(manifest) => self.load_assets(&progress, manifest),
+            JobRequest::ReloadTexture(texture, path, processors) => {
+                Some(self.reload_texture(texture, &path, &processors))
+            }
+            JobRequest::Run(job) => Some(FinishedJob::Finished { token, result: job(&progress) }),
+        };
+        // Deciding under the lock makes sure a job is either cancelled or
+        // completed, never both.
+        let mut state = self.inner.state.lock().unwrap();
+        state.running.remove(&token);
+        let result = match result {
+            Some(result) if !state.cancelled.contains(&token) => {
+                if counted {
+                    state.completed += 1;
+                }
+                result
+            }
+            _ => FinishedJob::Cancelled(token),
+        };
+        self.result_sender.send(result).ok();
+        drop(state);
+        (self.wake_up)();
+    }
+
+    /// Returns `None` if the job was cancelled part way.
     fn load_texture_from_file(
-        &mut self,
-        token: JobToken,
+        &self,
+        progress: &JobProgress<'_>,
+        path: &Path,
+        processors: &TextureProcessors,
+    ) -> Option<FinishedJob> {
+        let token = progress.token;
+        match self.texture_from_file(progress, path, processors) {
+            Ok(Some((texture, source))) => Some(FinishedJob::TextureLoaded { token, texture, source }),
+            Ok(None) => None,
+            Err(error) => Some(FinishedJob::Failed { token, error }),
+        }
+    }
+
+    fn texture_from_file(
+        &self,
+        progress: &JobProgress<'_>,
         path: &Path,
-        processors: &[&dyn TextureProcessor],
-    ) {
-        let texture = Texture::from_file(&self.gpu_device, &self.gpu_queue, path, processors);
+        processors: &TextureProcessors,
+    ) -> Result<Option<(BindableTexture, Option<TextureSource>)>, LoadError> {
+        let source = if self.hot_reload {
+            Some(TextureSource::new(&self.inner.vfs, path, processors))
+        } else {
+            None
+        };
+        let image = Texture::try_open_image(&self.inner.vfs, path)
+            .map_err(|e| LoadError::Image(path.to_owned(), e))?;
+        if progress.is_cancelled() {
+            return Ok(None);
+        }
+        // Decoding takes most of the time, uploading is quick.
+        progress.set(0.8);
+        let label = &path.display().to_string();
+        let texture = Texture::from_image(&self.gpu_device, &self.gpu_queue, &image, label, processors);
+        progress.set(1.0);
         let texture = BindableTexture::from_texture(texture, &self.gpu_device, &self.texture_bind_group_layout);
-        self.result_sender.send(FinishedJob::TextureLoaded { token, texture }).unwrap();
+        Ok(Some((texture, source)))
+    }
+
+    /// Failures are only printed and keep the old texture, files are often
+    /// saved half-written or broken while someone is working on them.
+    fn reload_texture(
+        &self,
+        texture: crate::Texture,
+        path: &Path,
+        processors: &TextureProcessors,
+    ) -> FinishedJob {
+        let reloaded = match Texture::try_open_image(&self.inner.vfs, path) {
+            Ok(image) => {
+                let label = &path.display().to_string();
+                let reloaded = Texture::from_image(&self.gpu_device, &self.gpu_queue, &image, label, processors);
+                Some(BindableTexture::from_texture(reloaded, &self.gpu_device, &self.texture_bind_group_layout))
+            }
+            Err(e) => {
+                eprintln!("failed to reload {}: {}", path.display(), e);
+                None
+            }
+        };
+        FinishedJob::TextureReloaded { texture, reloaded }
     }
 
     fn load_texture_from_rgba(
-        &mut self,
+        &self,
         token: JobToken,
         rgba: Vec<u8>,
         width: u32,
         height: u32,
-        processors: &[&dyn TextureProcessor],
-    ) {
+        processors: &TextureProcessors,
+    ) -> FinishedJob {
         let texture = Texture::from_rgba(&self.gpu_device, &self.gpu_queue, rgba, width, height, None, processors);
         let texture = BindableTexture::from_texture(texture, &self.gpu_device, &self.texture_bind_group_layout);
-        self.result_sender.send(FinishedJob::TextureLoaded { token, texture }).unwrap();
+        FinishedJob::TextureLoaded { token, texture, source: None }
+    }
+
+    fn load_texture_from_bytes(
+        &self,
+        token: JobToken,
+        bytes: &[u8],
+        processors: &TextureProcessors,
+    ) -> FinishedJob {
+        let label = "texture from memory";
+        match Texture::from_bytes(&self.gpu_device, &self.gpu_queue, bytes, label, processors) {
+            Ok(texture) => {
+                let texture = BindableTexture::from_texture(texture, &self.gpu_device, &self.texture_bind_group_layout);
+                FinishedJob::TextureLoaded { token, texture, source: None }
+            }
+            Err(e) => FinishedJob::Failed { token, error: LoadError::ImageBytes(e) },
+        }
+    }
+
+    fn load_sound_from_file(&self, progress: &JobProgress<'_>, path: &Path) -> Option<FinishedJob> {
+        let token = progress.token;
+        match decode::decode_file(&self.inner.vfs, path, progress) {
+            Ok(Some(sound)) => Some(FinishedJob::SoundLoaded { token, sound }),
+            Ok(None) => None,
+            Err(e) => Some(FinishedJob::Failed { token, error: LoadError::Sound(path.to_owned(), e) }),
+        }
+    }
+
+    /// Loads the assets one after the other, each taking an equal share of
+    /// the progress. The first asset that fails to load fails the whole job.
+    fn load_assets(&self, progress: &JobProgress<'_>, manifest: Manifest) -> Option<FinishedJob> {
+        let token = progress.token;
+        let count = manifest.len() as f32;
+        let mut assets = Vec::with_capacity(manifest.len());
+        for (i, (name, asset)) in manifest.assets.into_iter().enumerate() {
+            if progress.is_cancelled() {
+                return None;
+            }
+            let part = progress.part(i as f32 / count, (i + 1) as f32 / count);
+            match self.load_asset(&part, asset) {
+                Ok(Some(asset)) => assets.push((name, asset)),
+                Ok(None) => return None,
+                Err(error) => return Some(FinishedJob::Failed { token, error }),
+            }
+        }
+        Some(FinishedJob::AssetsLoaded { token, assets: LoadedAssets { assets } })
+    }
+
+    /// Returns `Ok(None)` if the job was cancelled part way.
+    fn load_asset(&self, progress: &JobProgress<'_>, asset: Asset) -> Result<Option<LoadedAsset>, LoadError> {
+        let asset = match asset {
+            Asset::Texture(path, processors) => {
+                match self.texture_from_file(progress, &path, &processors)? {
+                    Some((texture, source)) => LoadedAsset::Texture(texture, source),
+                    None => return Ok(None),
+                }
+            }
+            Asset::SpriteSheet(path, columns, rows) => {
+                match self.texture_from_file(progress, &path, &TextureProcessors::new())? {
+                    Some((texture, source)) => LoadedAsset::SpriteSheet(texture, source, columns, rows),
+                    None => return Ok(None),
+                }
+            }
+            Asset::Sound(path) => {
+                match decode::decode_file(&self.inner.vfs, &path, progress) {
+                    Ok(Some(sound)) => LoadedAsset::Sound(sound),
+                    Ok(None) => return Ok(None),
+                    Err(e) => return Err(LoadError::Sound(path, e)),
+                }
+            }
+            Asset::Data(path) => {
+                let data = self.inner.vfs.read(&path).map_err(|e| LoadError::Data(path, e))?;
+                progress.set(1.0);
+                LoadedAsset::Data(data)
+            }
+        };
+        Ok(Some(asset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reload(queue: &JobQueue) -> bool {
+        queue.reload_texture(crate::Texture { index: 0 }, PathBuf::from("a.png"), TextureProcessors::new())
+    }
+
+    #[test]
+    fn reloads_are_not_counted() {
+        let queue = detached_queue(Vfs::new());
+        queue.spawn(|| ());
+        queue.load_sound_from_file(PathBuf::from("jump.wav"));
+        assert!(reload(&queue));
+        assert_eq!(queue.counts(), JobCounts { pending: 2, running: 0, completed: 0 });
+
+        for _ in 0..3 {
+            queue.inner.next_job().unwrap();
+        }
+        assert_eq!(queue.counts(), JobCounts { pending: 0, running: 2, completed: 0 });
+    }
+
+    #[test]
+    fn nothing_is_queued_while_quitting() {
+        let queue = detached_queue(Vfs::new());
+        queue.quit();
+        queue.spawn(|| ());
+        assert!(!reload(&queue));
+        assert_eq!(queue.counts(), JobCounts::default());
+        assert!(queue.inner.next_job().is_none());
+    }
+
+    #[test]
+    fn priorities() {
+        let queue = detached_queue(Vfs::new());
+        let first = queue.spawn(|| ());
+        let background = queue.with_priority(JobPriority::Background).spawn(|| ());
+        let second = queue.spawn(|| ());
+        let high = queue.with_priority(JobPriority::High).spawn(|| ());
+        let order = std::iter::from_fn(|| queue.inner.next_job().map(|(token, _)| token))
+            .take(4)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [high, first, second, background]);
+    }
+
+    #[test]
+    fn load_errors() {
+        let error = LoadError::ImageBytes(image::load_from_memory(b"not an image").unwrap_err());
+        assert!(error.to_string().starts_with("failed to load texture from memory: "));
+        assert!(error.source().is_some());
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let error = LoadError::Data(PathBuf::from("levels/1.txt"), missing);
+        assert_eq!(error.to_string(), "failed to load levels/1.txt: gone");
     }
 }
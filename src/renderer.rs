@@ -8,6 +8,7 @@ pub struct Renderer<'a> {
     wgpu_state: &'a mut WgpuState,
     screen_size: (u32, u32),
     view: Rect,
+    overlay: bool,
 }
 
 impl<'a> Renderer<'a> {
@@ -24,6 +25,7 @@ impl<'a> Renderer<'a> {
             wgpu_state,
             screen_size,
             view,
+            overlay: false,
         }
     }
 
@@ -38,6 +40,7 @@ impl<'a> Renderer<'a> {
         };
         self.wgpu_state.instances.push(Instance {
             texture,
+            overlay: self.overlay,
             raw: RawInstance {
                 pos,
                 size,
@@ -66,9 +69,30 @@ impl<'a> Renderer<'a> {
         self.view = view;
     }
 
+    pub fn view(&self) -> Rect {
+        self.view
+    }
+
     pub fn screen_size(&self) -> (u32, u32) {
         self.screen_size
     }
+
+    /// Switches to screen coordinates for drawing built-in overlays on top
+    /// of everything the game drew.
+    pub(crate) fn begin_overlay(&mut self) {
+        self.overlay = true;
+        self.view = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: self.screen_size.0 as f32,
+            h: self.screen_size.1 as f32,
+        };
+    }
+
+    pub(crate) fn fill_rect(&mut self, dest: Rect, color: (u8, u8, u8)) {
+        let pixel = Texture { index: 0 };
+        self.draw(pixel, dest).color(color.0, color.1, color.2);
+    }
 }
 
 pub struct DrawBuilder<'a> {
@@ -0,0 +1,461 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+use crate::{
+    gamepad::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId},
+    input::{Input, InputEvent, Key, Modifiers, MouseButton, Touch, TouchPhase},
+};
+
+const MAGIC: &[u8; 8] = b"BARFREC\x01";
+
+// Recording format: the magic header followed by one entry per tick. Each
+// entry is a varint event count followed by that many events, every event
+// starting with one of the tags below. The first entry holds a snapshot of
+// the input state at the moment recording started.
+const TAG_KEY_PRESSED: u8 = 0;
+const TAG_KEY_RELEASED: u8 = 1;
+const TAG_MODIFIERS: u8 = 2;
+const TAG_MOUSE_PRESSED: u8 = 3;
+const TAG_MOUSE_RELEASED: u8 = 4;
+const TAG_MOUSE_MOVED: u8 = 5;
+const TAG_MOUSE_LEFT: u8 = 6;
+const TAG_MOUSE_SCROLLED: u8 = 7;
+const TAG_RELEASE_ALL: u8 = 8;
+const TAG_PAD_CONNECTED: u8 = 9;
+const TAG_PAD_DISCONNECTED: u8 = 10;
+const TAG_PAD_PRESSED: u8 = 11;
+const TAG_PAD_RELEASED: u8 = 12;
+const TAG_PAD_AXIS: u8 = 13;
+const TAG_TOUCH: u8 = 14;
+const TAG_CLEAR_EVENTS: u8 = 15;
+
+/// Writes the input seen by every update into a file.
+pub(crate) struct Recorder {
+    out: BufWriter<File>,
+    tick: Vec<InputEvent>,
+    buf: Vec<u8>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path, input: &Input) -> io::Result<Recorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        let mut recorder = Recorder {
+            out,
+            tick: input.snapshot(),
+            buf: Vec::new(),
+        };
+        recorder.end_tick()?;
+        Ok(recorder)
+    }
+
+    pub(crate) fn record(&mut self, event: InputEvent) {
+        self.tick.push(event);
+    }
+
+    pub(crate) fn end_tick(&mut self) -> io::Result<()> {
+        self.buf.clear();
+        write_varint(&mut self.buf, self.tick.len() as u64);
+        for event in self.tick.drain(..) {
+            encode_event(&mut self.buf, event);
+        }
+        self.out.write_all(&self.buf)
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A recorded session that can be fed back into `Input` tick by tick.
+pub struct Replay {
+    ticks: Vec<Vec<InputEvent>>,
+    next: usize,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let bytes = std::fs::read(path).map_err(ReplayError::Io)?;
+        Replay::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::Format("not an input recording"));
+        }
+        let mut ticks = Vec::new();
+        while !reader.bytes.is_empty() {
+            let count = reader.varint()?;
+            let mut events = Vec::new();
+            for _ in 0..count {
+                events.push(reader.event()?);
+            }
+            ticks.push(events);
+        }
+        if ticks.is_empty() {
+            return Err(ReplayError::Format("missing initial snapshot"));
+        }
+        Ok(Replay { ticks, next: 0 })
+    }
+
+    /// Number of updates covered by the recording.
+    pub fn len(&self) -> usize {
+        self.ticks.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.ticks.len()
+    }
+
+    /// Applies the input of the next update. The first call also resets
+    /// `input` to the state it had when recording started. Returns `false`
+    /// once the recording is exhausted.
+    pub fn advance(&mut self, input: &mut Input) -> bool {
+        if self.next == 0 {
+            input.reset();
+            self.apply(0, input);
+            // the snapshot restores state, it is not input of the first update
+            input.clear_events();
+        }
+        if self.is_finished() {
+            return false;
+        }
+        self.apply(self.next, input);
+        true
+    }
+
+    fn apply(&mut self, tick: usize, input: &mut Input) {
+        for &event in &self.ticks[tick] {
+            input.apply(event);
+        }
+        self.next = tick + 1;
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Format(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "failed to read input recording: {}", e),
+            ReplayError::Format(msg) => write!(f, "invalid input recording: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn encode_event(buf: &mut Vec<u8>, event: InputEvent) {
+    match event {
+        InputEvent::KeyPressed(key) => {
+            buf.push(TAG_KEY_PRESSED);
+            write_varint(buf, key as u64);
+        }
+        InputEvent::KeyReleased(key) => {
+            buf.push(TAG_KEY_RELEASED);
+            write_varint(buf, key as u64);
+        }
+        InputEvent::ModifiersChanged(Modifiers { shift, ctrl, alt, logo }) => {
+            buf.push(TAG_MODIFIERS);
+            buf.push(u8::from(shift) | u8::from(ctrl) << 1 | u8::from(alt) << 2 | u8::from(logo) << 3);
+        }
+        InputEvent::MousePressed(button) => {
+            buf.push(TAG_MOUSE_PRESSED);
+            buf.push(mouse_button_index(button));
+        }
+        InputEvent::MouseReleased(button) => {
+            buf.push(TAG_MOUSE_RELEASED);
+            buf.push(mouse_button_index(button));
+        }
+        InputEvent::MouseMoved(Some((x, y))) => {
+            buf.push(TAG_MOUSE_MOVED);
+            write_varint(buf, x as u64);
+            write_varint(buf, y as u64);
+        }
+        InputEvent::MouseMoved(None) => buf.push(TAG_MOUSE_LEFT),
+        InputEvent::MouseScrolled(scroll) => {
+            buf.push(TAG_MOUSE_SCROLLED);
+            buf.extend_from_slice(&scroll.to_le_bytes());
+        }
+        InputEvent::ReleaseAll => buf.push(TAG_RELEASE_ALL),
+        InputEvent::Gamepad(GamepadEvent::Connected(GamepadId(id))) => {
+            buf.push(TAG_PAD_CONNECTED);
+            write_varint(buf, id as u64);
+        }
+        InputEvent::Gamepad(GamepadEvent::Disconnected(GamepadId(id))) => {
+            buf.push(TAG_PAD_DISCONNECTED);
+            write_varint(buf, id as u64);
+        }
+        InputEvent::Gamepad(GamepadEvent::ButtonPressed(GamepadId(id), button)) => {
+            buf.push(TAG_PAD_PRESSED);
+            write_varint(buf, id as u64);
+            buf.push(button as u8);
+        }
+        InputEvent::Gamepad(GamepadEvent::ButtonReleased(GamepadId(id), button)) => {
+            buf.push(TAG_PAD_RELEASED);
+            write_varint(buf, id as u64);
+            buf.push(button as u8);
+        }
+        InputEvent::Gamepad(GamepadEvent::AxisChanged(GamepadId(id), axis, value)) => {
+            buf.push(TAG_PAD_AXIS);
+            write_varint(buf, id as u64);
+            let axis = GamepadAxis::ALL.iter().position(|&a| a == axis).unwrap();
+            buf.push(axis as u8);
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        InputEvent::Touch(Touch { id, phase, pos: (x, y) }) => {
+            buf.push(TAG_TOUCH);
+            write_varint(buf, id);
+            buf.push(match phase {
+                TouchPhase::Started => 0,
+                TouchPhase::Moved => 1,
+                TouchPhase::Held => 2,
+                TouchPhase::Ended => 3,
+                TouchPhase::Cancelled => 4,
+            });
+            write_varint(buf, x as u64);
+            write_varint(buf, y as u64);
+        }
+        InputEvent::ClearEvents => buf.push(TAG_CLEAR_EVENTS),
+    }
+}
+
+fn mouse_button_index(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Format("unexpected end of file"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err(ReplayError::Format("varint too long"));
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(f32::from_le_bytes(bytes))
+    }
+
+    fn key(&mut self) -> Result<Key, ReplayError> {
+        let idx = self.varint()? as usize;
+        Key::ALL.get(idx).copied().ok_or(ReplayError::Format("unknown key"))
+    }
+
+    fn mouse_button(&mut self) -> Result<MouseButton, ReplayError> {
+        match self.byte()? {
+            0 => Ok(MouseButton::Left),
+            1 => Ok(MouseButton::Right),
+            _ => Err(ReplayError::Format("unknown mouse button")),
+        }
+    }
+
+    fn gamepad(&mut self) -> Result<GamepadId, ReplayError> {
+        Ok(GamepadId(self.varint()? as usize))
+    }
+
+    fn gamepad_button(&mut self) -> Result<GamepadButton, ReplayError> {
+        let idx = self.byte()? as usize;
+        GamepadButton::ALL.get(idx).copied().ok_or(ReplayError::Format("unknown gamepad button"))
+    }
+
+    fn gamepad_axis(&mut self) -> Result<GamepadAxis, ReplayError> {
+        let idx = self.byte()? as usize;
+        GamepadAxis::ALL.get(idx).copied().ok_or(ReplayError::Format("unknown gamepad axis"))
+    }
+
+    fn event(&mut self) -> Result<InputEvent, ReplayError> {
+        Ok(match self.byte()? {
+            TAG_KEY_PRESSED => InputEvent::KeyPressed(self.key()?),
+            TAG_KEY_RELEASED => InputEvent::KeyReleased(self.key()?),
+            TAG_MODIFIERS => {
+                let bits = self.byte()?;
+                InputEvent::ModifiersChanged(Modifiers {
+                    shift: bits & 1 != 0,
+                    ctrl: bits & 2 != 0,
+                    alt: bits & 4 != 0,
+                    logo: bits & 8 != 0,
+                })
+            }
+            TAG_MOUSE_PRESSED => InputEvent::MousePressed(self.mouse_button()?),
+            TAG_MOUSE_RELEASED => InputEvent::MouseReleased(self.mouse_button()?),
+            TAG_MOUSE_MOVED => {
+                let x = self.varint()? as u32;
+                let y = self.varint()? as u32;
+                InputEvent::MouseMoved(Some((x, y)))
+            }
+            TAG_MOUSE_LEFT => InputEvent::MouseMoved(None),
+            TAG_MOUSE_SCROLLED => InputEvent::MouseScrolled(self.f32()?),
+            TAG_RELEASE_ALL => InputEvent::ReleaseAll,
+            TAG_PAD_CONNECTED => InputEvent::Gamepad(GamepadEvent::Connected(self.gamepad()?)),
+            TAG_PAD_DISCONNECTED => InputEvent::Gamepad(GamepadEvent::Disconnected(self.gamepad()?)),
+            TAG_PAD_PRESSED => {
+                let id = self.gamepad()?;
+                InputEvent::Gamepad(GamepadEvent::ButtonPressed(id, self.gamepad_button()?))
+            }
+            TAG_PAD_RELEASED => {
+                let id = self.gamepad()?;
+                InputEvent::Gamepad(GamepadEvent::ButtonReleased(id, self.gamepad_button()?))
+            }
+            TAG_PAD_AXIS => {
+                let id = self.gamepad()?;
+                let axis = self.gamepad_axis()?;
+                InputEvent::Gamepad(GamepadEvent::AxisChanged(id, axis, self.f32()?))
+            }
+            TAG_TOUCH => {
+                let id = self.varint()?;
+                let phase = match self.byte()? {
+                    0 => TouchPhase::Started,
+                    1 => TouchPhase::Moved,
+                    2 => TouchPhase::Held,
+                    3 => TouchPhase::Ended,
+                    4 => TouchPhase::Cancelled,
+                    _ => return Err(ReplayError::Format("unknown touch phase")),
+                };
+                let x = self.varint()? as u32;
+                let y = self.varint()? as u32;
+                InputEvent::Touch(Touch { id, phase, pos: (x, y) })
+            }
+            TAG_CLEAR_EVENTS => InputEvent::ClearEvents,
+            _ => return Err(ReplayError::Format("unknown event")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(input: &Input) -> String {
+        format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            Key::ALL.iter().filter(|&&k| input.is_pressed(k)).collect::<Vec<_>>(),
+            input.key_events(),
+            input.modifiers(),
+            input.mouse_pos(),
+            input.scroll_pos(),
+            input
+                .gamepads()
+                .map(|(id, pad)| {
+                    (id, pad.is_pressed(GamepadButton::South), pad.raw_axis(GamepadAxis::LeftStickX))
+                })
+                .collect::<Vec<_>>(),
+            input.touches(),
+        )
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let pad = GamepadId(3);
+        let ticks = vec![
+            vec![InputEvent::KeyPressed(Key::A), InputEvent::MouseMoved(Some((10, 20)))],
+            vec![],
+            vec![
+                InputEvent::KeyPressed(Key::A),
+                InputEvent::ModifiersChanged(Modifiers::SHIFT),
+                InputEvent::MouseScrolled(-1.5),
+                InputEvent::Gamepad(GamepadEvent::Connected(pad)),
+                InputEvent::Gamepad(GamepadEvent::ButtonPressed(pad, GamepadButton::South)),
+                InputEvent::Gamepad(GamepadEvent::AxisChanged(pad, GamepadAxis::LeftStickX, 0.25)),
+            ],
+            vec![
+                InputEvent::KeyReleased(Key::Space),
+                InputEvent::MousePressed(MouseButton::Right),
+                InputEvent::Touch(Touch { id: 7, phase: TouchPhase::Started, pos: (5, 6) }),
+            ],
+            vec![InputEvent::ReleaseAll],
+        ];
+
+        let path = std::env::temp_dir().join(format!("barf-replay-test-{}", std::process::id()));
+        let mut input = Input::default();
+        input.apply(InputEvent::KeyPressed(Key::Space));
+        input.clear_events();
+
+        let mut recorder = Recorder::create(&path, &input).unwrap();
+        let mut expected = Vec::new();
+        for tick in &ticks {
+            for &event in tick {
+                input.apply(event);
+                recorder.record(event);
+            }
+            expected.push(state(&input));
+            recorder.end_tick().unwrap();
+            input.clear_events();
+        }
+        recorder.finish().unwrap();
+
+        let mut replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.len(), ticks.len());
+
+        // leftover state in the target input must not leak into the replay
+        let mut input = Input::default();
+        input.apply(InputEvent::KeyPressed(Key::Escape));
+        for expected in &expected {
+            assert!(replay.advance(&mut input));
+            assert_eq!(&state(&input), expected);
+            input.clear_events();
+        }
+        assert!(!replay.advance(&mut input));
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(Replay::from_bytes(b"nope"), Err(ReplayError::Format(_))));
+        assert!(matches!(Replay::from_bytes(MAGIC), Err(ReplayError::Format(_))));
+        let mut truncated = MAGIC.to_vec();
+        truncated.extend_from_slice(&[2, TAG_KEY_PRESSED]);
+        assert!(matches!(Replay::from_bytes(&truncated), Err(ReplayError::Format(_))));
+    }
+}
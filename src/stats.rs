@@ -0,0 +1,83 @@
+use std::{
+    collections::VecDeque,
+    time::Duration,
+};
+use crate::{Rect, Renderer};
+
+const HISTORY_LEN: usize = 100;
+
+/// Where the time of a single frame went.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameStats {
+    /// Wall clock time since the previous frame started.
+    pub frame_time: Duration,
+    /// Time spent in `Game::update`, summed over all updates of the frame.
+    pub update_time: Duration,
+    pub draw_time: Duration,
+    /// Time spent building and submitting GPU commands.
+    pub render_time: Duration,
+    /// Time the GPU spent on the frame, if the adapter supports timestamp
+    /// queries. This lags a few frames behind.
+    pub gpu_time: Option<Duration>,
+    pub updates: u32,
+    /// Time skipped because the game fell too far behind, see
+    /// `Config::max_backlog`.
+    pub dropped_time: Duration,
+    pub instances: usize,
+    /// Number of draw calls, one per run of instances sharing a texture.
+    pub batches: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct FrameHistory {
+    frames: VecDeque<FrameStats>,
+}
+
+impl FrameHistory {
+    pub(crate) fn push(&mut self, stats: FrameStats) {
+        if self.frames.len() == HISTORY_LEN {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(stats);
+    }
+
+    pub(crate) fn last(&self) -> FrameStats {
+        self.frames.back().copied().unwrap_or_default()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &FrameStats> {
+        self.frames.iter()
+    }
+}
+
+const BAR_WIDTH: f32 = 2.0;
+const PIXELS_PER_MS: f32 = 4.0;
+const MAX_BAR_HEIGHT: f32 = 200.0;
+
+/// Draws a bar per frame in the bottom left corner, split into update
+/// (green), draw (blue) and render (red) time, with a white line marking
+/// the frame budget, `budget` seconds.
+pub(crate) fn draw_overlay(history: &FrameHistory, budget: f32, renderer: &mut Renderer<'_>) {
+    renderer.begin_overlay();
+    let bottom = renderer.screen_size().1 as f32;
+    for (idx, frame) in history.iter().enumerate() {
+        let x = idx as f32 * BAR_WIDTH;
+        let mut y = bottom;
+        let parts = [
+            (frame.update_time, (80, 220, 80)),
+            (frame.draw_time, (80, 120, 240)),
+            (frame.render_time, (230, 70, 70)),
+        ];
+        for &(time, color) in parts.iter() {
+            let h = (time.as_secs_f32() * 1000.0 * PIXELS_PER_MS).min(y - (bottom - MAX_BAR_HEIGHT));
+            if h <= 0.0 {
+                continue;
+            }
+            y -= h;
+            renderer.fill_rect(Rect { x, y, w: BAR_WIDTH, h }, color);
+        }
+    }
+    let budget = (budget * 1000.0 * PIXELS_PER_MS).min(MAX_BAR_HEIGHT);
+    let w = HISTORY_LEN as f32 * BAR_WIDTH;
+    renderer.fill_rect(Rect { x: 0.0, y: bottom - budget, w, h: 1.0 }, (255, 255, 255));
+}
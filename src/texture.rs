@@ -1,14 +1,109 @@
-use std::path::Path;
+use std::{io::BufReader, iter::FromIterator, path::Path, sync::Arc};
 use image::GenericImageView;
+use crate::Vfs;
 
 pub trait TextureProcessor: Send + Sync {
-    fn process_texture(&self, rgba: &mut [u8]);
+    /// `rgba` holds `width * height` pixels, row by row from the top.
+    fn process_texture(&self, rgba: &mut [u8], width: u32, height: u32);
+}
+
+/// Lets processors in statics be shared without copying them.
+struct StaticProcessor<T: TextureProcessor + ?Sized + 'static>(&'static T);
+
+impl<T: TextureProcessor + ?Sized> TextureProcessor for StaticProcessor<T> {
+    fn process_texture(&self, rgba: &mut [u8], width: u32, height: u32) {
+        self.0.process_texture(rgba, width, height)
+    }
+}
+
+/// Processors that run on a texture in order before it is uploaded. Cheap
+/// to clone, the processors themselves are shared.
+///
+/// Build one with `with`, collect it from an iterator of processors, or
+/// convert a `Vec` of boxed processors or a static array of them:
+/// `TextureProcessors::from(&[MakeTransparent(255, 0, 255)])`.
+#[derive(Clone, Default)]
+pub struct TextureProcessors {
+    processors: Vec<Arc<dyn TextureProcessor>>,
+}
+
+impl TextureProcessors {
+    pub fn new() -> TextureProcessors {
+        TextureProcessors::default()
+    }
+
+    pub fn with(mut self, processor: impl TextureProcessor + 'static) -> TextureProcessors {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub(crate) fn run(&self, rgba: &mut [u8], width: u32, height: u32) {
+        for processor in &self.processors {
+            processor.process_texture(rgba, width, height);
+        }
+    }
+}
+
+impl From<Vec<Box<dyn TextureProcessor>>> for TextureProcessors {
+    fn from(processors: Vec<Box<dyn TextureProcessor>>) -> TextureProcessors {
+        processors.into_iter().collect()
+    }
+}
+
+impl From<Vec<Arc<dyn TextureProcessor>>> for TextureProcessors {
+    fn from(processors: Vec<Arc<dyn TextureProcessor>>) -> TextureProcessors {
+        TextureProcessors { processors }
+    }
+}
+
+impl<T: TextureProcessor + 'static> From<Vec<Box<T>>> for TextureProcessors {
+    fn from(processors: Vec<Box<T>>) -> TextureProcessors {
+        processors.into_iter().map(|p| Arc::from(p as Box<dyn TextureProcessor>)).collect()
+    }
+}
+
+impl From<&'static [&'static dyn TextureProcessor]> for TextureProcessors {
+    fn from(processors: &'static [&'static dyn TextureProcessor]) -> TextureProcessors {
+        processors.iter().map(|&p| StaticProcessor(p)).collect()
+    }
+}
+
+impl<T: TextureProcessor + 'static, const N: usize> From<&'static [T; N]> for TextureProcessors {
+    fn from(processors: &'static [T; N]) -> TextureProcessors {
+        processors.iter().map(StaticProcessor).collect()
+    }
+}
+
+impl<T: TextureProcessor + 'static> FromIterator<T> for TextureProcessors {
+    fn from_iter<I: IntoIterator<Item = T>>(processors: I) -> TextureProcessors {
+        processors.into_iter().map(|p| Arc::new(p) as Arc<dyn TextureProcessor>).collect()
+    }
+}
+
+impl FromIterator<Box<dyn TextureProcessor>> for TextureProcessors {
+    fn from_iter<I: IntoIterator<Item = Box<dyn TextureProcessor>>>(processors: I) -> TextureProcessors {
+        processors.into_iter().map(Arc::from).collect()
+    }
+}
+
+impl FromIterator<Arc<dyn TextureProcessor>> for TextureProcessors {
+    fn from_iter<I: IntoIterator<Item = Arc<dyn TextureProcessor>>>(processors: I) -> TextureProcessors {
+        TextureProcessors { processors: processors.into_iter().collect() }
+    }
 }
 
 pub struct MakeTransparent(pub u8, pub u8, pub u8);
 
 impl TextureProcessor for MakeTransparent {
-    fn process_texture(&self, rgba: &mut [u8]) {
+    fn process_texture(&self, rgba: &mut [u8], _width: u32, _height: u32) {
         for pixel in rgba.chunks_mut(4) {
             match *pixel {
                 [r, g, b, 255] if r == self.0 && g == self.1 && b == self.2 => {
@@ -30,37 +125,35 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn from_file(
+    /// The format comes from the extension, or the contents if that fails.
+    pub fn try_open_image(vfs: &Vfs, path: &Path) -> image::ImageResult<image::DynamicImage> {
+        let mut reader = image::io::Reader::new(BufReader::new(vfs.open(path)?));
+        match image::ImageFormat::from_path(path) {
+            Ok(format) => reader.set_format(format),
+            Err(_) => reader = reader.with_guessed_format()?,
+        }
+        reader.decode()
+    }
+
+    /// Decodes an image file that is already in memory, in any format
+    /// `image` can guess from its contents.
+    pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        path: &Path,
-        processors: &[&dyn TextureProcessor],
-    ) -> Texture {
-        let image = image::io::Reader::open(path)
-            .unwrap_or_else(|e| panic!("failed to open {}: {}", path.display(), e))
-            .decode()
-            .unwrap_or_else(|e| panic!("failed to load {}: {}", path.display(), e));
-        let label = &path.display().to_string();
-        Self::from_image(device, queue, &image, label, processors)
-    }
-
-    // pub fn from_bytes(
-    //     device: &wgpu::Device,
-    //     queue: &wgpu::Queue,
-    //     bytes: &[u8],
-    //     label: &str,
-    //     processors: &[&dyn TextureProcessor],
-    // ) -> Texture {
-    //     let img = image::load_from_memory(bytes).unwrap();
-    //     Self::from_image(device, queue, &img, label, processors)
-    // }
+        bytes: &[u8],
+        label: &str,
+        processors: &TextureProcessors,
+    ) -> image::ImageResult<Texture> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(device, queue, &img, label, processors))
+    }
 
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         image: &image::DynamicImage,
         label: &str,
-        processors: &[&dyn TextureProcessor],
+        processors: &TextureProcessors,
     ) -> Texture {
         let (width, height) = image.dimensions();
         let rgba = image.to_rgba8().into_raw();
@@ -74,12 +167,10 @@ impl Texture {
         width: u32,
         height: u32,
         label: Option<&str>,
-        processors: &[&dyn TextureProcessor],
+        processors: &TextureProcessors,
     ) -> Texture {
         assert_eq!((width * height * 4) as usize, rgba.len());
-        for processor in processors {
-            processor.process_texture(&mut rgba);
-        }
+        processors.run(&mut rgba, width, height);
         let rgba = rgba.as_slice();
         let size = wgpu::Extent3d {
             width,
@@ -201,3 +292,57 @@ impl BindableTexture {
         BindableTexture { texture, bind_group }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mirrors the image top to bottom, which needs the image size.
+    struct FlipVertically;
+
+    impl TextureProcessor for FlipVertically {
+        fn process_texture(&self, rgba: &mut [u8], width: u32, height: u32) {
+            let row = width as usize * 4;
+            for y in 0..height as usize / 2 {
+                let (top, bottom) = rgba.split_at_mut((height as usize - 1 - y) * row);
+                top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+            }
+        }
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    /// A 1x3 image, red on top of two blue pixels.
+    fn run(processors: &TextureProcessors) -> Vec<u8> {
+        let mut rgba = [RED, BLUE, BLUE].concat();
+        processors.run(&mut rgba, 1, 3);
+        rgba
+    }
+
+    #[test]
+    fn processors_run_in_order() {
+        let processors = TextureProcessors::new().with(FlipVertically).with(MakeTransparent(255, 0, 0));
+        assert_eq!(processors.len(), 2);
+        assert_eq!(run(&processors), [BLUE, BLUE, CLEAR].concat());
+        assert_eq!(run(&TextureProcessors::new()), [RED, BLUE, BLUE].concat());
+    }
+
+    #[test]
+    fn conversions() {
+        static MIXED: &[&dyn TextureProcessor] = &[&MakeTransparent(0, 0, 255), &FlipVertically];
+        let flipped_clear = [CLEAR, CLEAR, RED].concat();
+        assert_eq!(run(&TextureProcessors::from(MIXED)), flipped_clear);
+        assert_eq!(run(&TextureProcessors::from(&[MakeTransparent(0, 0, 255)])), [RED, CLEAR, CLEAR].concat());
+        assert_eq!(run(&vec![Box::new(FlipVertically)].into()), [BLUE, BLUE, RED].concat());
+
+        let boxed: Vec<Box<dyn TextureProcessor>> = vec![Box::new(MakeTransparent(0, 0, 255)), Box::new(FlipVertically)];
+        assert_eq!(run(&boxed.into()), flipped_clear);
+
+        // processors made at runtime don't need to be static
+        let chosen = vec![(255, 0, 0), (0, 0, 255)];
+        let processors: TextureProcessors = chosen.into_iter().map(|(r, g, b)| MakeTransparent(r, g, b)).collect();
+        assert_eq!(run(&processors), [CLEAR, CLEAR, CLEAR].concat());
+    }
+}
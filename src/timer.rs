@@ -1,39 +1,179 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-const FPS: f32 = 60.0;
-const TIME_PER_FRAME: f32 = 1.0 / FPS;
-const MAX_TIME_BACKLOG: f32 = 0.5;
+pub(crate) const DEFAULT_TICK_RATE: f32 = 60.0;
+pub(crate) const DEFAULT_MAX_BACKLOG: f32 = 0.5;
+/// Beyond this a tick is too short for `Instant` to be of much use.
+pub(crate) const MAX_TICK_RATE: f32 = 10_000.0;
 
 pub(crate) struct Timer {
     remaining: f32,
     last_timestamp: Instant,
+    time_per_tick: f32,
+    max_backlog: f32,
+    ticks: u64,
+    time: f64,
+    paused: bool,
+    time_scale: f32,
+    pending_steps: u32,
 }
 
 impl Timer {
-    pub(crate) fn new() -> Timer {
+    pub(crate) fn new(tick_rate: f32, max_backlog: f32) -> Timer {
         Timer {
             remaining: 0.0,
             last_timestamp: Instant::now(),
+            time_per_tick: 1.0 / tick_rate,
+            max_backlog,
+            ticks: 0,
+            time: 0.0,
+            paused: false,
+            time_scale: 1.0,
+            pending_steps: 0,
         }
     }
 
-    pub(crate) fn tick(&mut self) {
+    /// Returns how much time, in seconds, was dropped because the backlog
+    /// grew past `max_backlog`.
+    pub(crate) fn tick(&mut self) -> f32 {
         let now = Instant::now();
         let elapsed = now.duration_since(self.last_timestamp);
         self.last_timestamp = now;
-        let elapsed = elapsed.as_secs_f32();
+        if self.paused {
+            return 0.0;
+        }
+        let elapsed = elapsed.as_secs_f32() * self.time_scale;
         self.remaining += elapsed;
-        if self.remaining > MAX_TIME_BACKLOG {
-            self.remaining = MAX_TIME_BACKLOG;
+        if self.remaining > self.max_backlog {
+            let dropped = self.remaining - self.max_backlog;
+            self.remaining = self.max_backlog;
+            dropped
+        } else {
+            0.0
         }
     }
 
     pub(crate) fn should_update(&mut self) -> bool {
-        if self.remaining >= TIME_PER_FRAME {
-            self.remaining -= TIME_PER_FRAME;
-            true
+        if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+        } else if self.paused || self.remaining < self.time_per_tick {
+            return false;
         } else {
-            false
+            self.remaining -= self.time_per_tick;
+        }
+        self.ticks += 1;
+        self.time += f64::from(self.time_per_tick);
+        true
+    }
+
+    /// Real time left until the next update is due, `None` if updates will
+    /// not run on their own because the timer is paused.
+    pub(crate) fn until_next_update(&self) -> Option<Duration> {
+        if self.pending_steps > 0 {
+            return Some(Duration::from_secs(0));
+        }
+        if self.paused || self.time_scale == 0.0 {
+            return None;
+        }
+        let remaining = (self.time_per_tick - self.remaining).max(0.0) / self.time_scale;
+        Some(Duration::from_secs_f32(remaining))
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            self.pending_steps = 0;
+        }
+    }
+
+    /// Queues a single update, only has an effect while paused.
+    pub(crate) fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub(crate) fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub(crate) fn set_time_scale(&mut self, time_scale: f32) {
+        assert!(
+            time_scale >= 0.0 && time_scale.is_finite(),
+            "time scale must be finite and not negative",
+        );
+        self.time_scale = time_scale;
+    }
+
+    /// How far the real time is between the last update and the next one,
+    /// in `0.0..=1.0`.
+    pub(crate) fn alpha(&self) -> f32 {
+        (self.remaining / self.time_per_tick).min(1.0)
+    }
+
+    pub(crate) fn tick_rate(&self) -> f32 {
+        1.0 / self.time_per_tick
+    }
+
+    pub(crate) fn set_tick_rate(&mut self, tick_rate: f32) {
+        assert!(
+            tick_rate > 0.0 && tick_rate <= MAX_TICK_RATE,
+            "tick rate must be positive and at most {}",
+            MAX_TICK_RATE,
+        );
+        self.time_per_tick = 1.0 / tick_rate;
+    }
+
+    pub(crate) fn time_per_tick(&self) -> f32 {
+        self.time_per_tick
+    }
+
+    pub(crate) fn max_backlog(&self) -> f32 {
+        self.max_backlog
+    }
+
+    pub(crate) fn set_max_backlog(&mut self, max_backlog: f32) {
+        assert!(
+            max_backlog > 0.0 && max_backlog.is_finite(),
+            "max backlog must be positive and finite",
+        );
+        self.max_backlog = max_backlog;
+    }
+
+    pub(crate) fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub(crate) fn time(&self) -> f64 {
+        self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_rate() {
+        let mut timer = Timer::new(DEFAULT_TICK_RATE, DEFAULT_MAX_BACKLOG);
+        timer.set_tick_rate(MAX_TICK_RATE);
+        assert_eq!(timer.tick_rate(), MAX_TICK_RATE);
+        timer.set_tick_rate(0.5);
+        assert_eq!(timer.time_per_tick(), 2.0);
+    }
+
+    #[test]
+    fn rejects_bad_tick_rates() {
+        for &tick_rate in &[0.0, -60.0, f32::INFINITY, f32::NAN, MAX_TICK_RATE * 2.0] {
+            let result = std::panic::catch_unwind(|| {
+                Timer::new(DEFAULT_TICK_RATE, DEFAULT_MAX_BACKLOG).set_tick_rate(tick_rate);
+            });
+            assert!(result.is_err(), "{} was accepted", tick_rate);
+            let result = std::panic::catch_unwind(|| crate::Config::new().tick_rate(tick_rate));
+            assert!(result.is_err(), "{} was accepted by Config", tick_rate);
         }
     }
 }
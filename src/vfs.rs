@@ -0,0 +1,743 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+/// A file opened through a `Vfs`.
+pub trait VfsFile: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> VfsFile for T {}
+
+/// Something that can be mounted into a `Vfs`, e.g. a directory or archive.
+///
+/// Paths handed to a mount are normalised: relative to the mount, separated
+/// by `/`, without `.` or `..`.
+pub trait Mount: Send + Sync {
+    /// Opens a file, `Ok(None)` if the mount doesn't have it.
+    fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>>;
+
+    /// Whether the mount has the file. Should be cheap, it runs on the main
+    /// thread for every watched file when hot reloading. The default opens
+    /// the file.
+    fn contains(&self, path: &str) -> bool {
+        matches!(self.open(path), Ok(Some(_)))
+    }
+
+    /// When the file last changed, if known. Used for `Config::hot_reload`.
+    fn modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Layers mounts on top of each other, so patches and mods can replace
+/// files of the base game. All loads of the `JobQueue` go through it.
+///
+/// Later mounts take priority. Absolute paths, and every path as long as
+/// nothing is mounted, go straight to the file system.
+#[derive(Clone, Default)]
+pub struct Vfs {
+    mounts: Arc<RwLock<Vec<Arc<dyn Mount>>>>,
+}
+
+impl fmt::Debug for Vfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vfs").field("mounts", &self.mounts.read().unwrap().len()).finish()
+    }
+}
+
+impl Vfs {
+    pub fn new() -> Vfs {
+        Vfs::default()
+    }
+
+    /// Mounts on top of everything mounted so far.
+    pub fn mount(&self, mount: impl Mount + 'static) {
+        self.mounts.write().unwrap().push(Arc::new(mount));
+    }
+
+    pub fn unmount_all(&self) {
+        self.mounts.write().unwrap().clear();
+    }
+
+    pub fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let mounts = match self.resolve(path) {
+            Some(mounts) => mounts,
+            None => return Ok(Box::new(BufReader::new(File::open(path)?))),
+        };
+        let normalized = normalize(path)?;
+        for mount in mounts.iter().rev() {
+            if let Some(file) = mount.open(&normalized)? {
+                return Ok(file);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in any mount", normalized)))
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// When the file last changed, from the mount that has it.
+    pub fn modified(&self, path: &Path) -> Option<SystemTime> {
+        let mounts = match self.resolve(path) {
+            Some(mounts) => mounts,
+            None => return std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        };
+        let normalized = normalize(path).ok()?;
+        for mount in mounts.iter().rev() {
+            if mount.contains(&normalized) {
+                return mount.modified(&normalized);
+            }
+        }
+        None
+    }
+
+    /// The mounts to look in, `None` if `path` is for the file system.
+    fn resolve(&self, path: &Path) -> Option<Vec<Arc<dyn Mount>>> {
+        let mounts = self.mounts.read().unwrap();
+        if mounts.is_empty() || path.is_absolute() {
+            return None;
+        }
+        Some(mounts.clone())
+    }
+}
+
+/// Turns a relative path into the form mounts expect. Fails for paths that
+/// would leave the mount with `..`.
+fn normalize(path: &Path) -> io::Result<String> {
+    let path = path.to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8"))?;
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(&['/', '\\'][..]) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    let message = format!("{} is outside of the mounts", path);
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// A directory on the file system.
+pub struct DirMount {
+    root: PathBuf,
+}
+
+impl DirMount {
+    pub fn new(root: impl Into<PathBuf>) -> DirMount {
+        DirMount { root: root.into() }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        let mut full = self.root.clone();
+        full.extend(path.split('/'));
+        full
+    }
+}
+
+impl Mount for DirMount {
+    fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>> {
+        match File::open(self.path(path)) {
+            Ok(file) => Ok(Some(Box::new(BufReader::new(file)))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.path(path).is_file()
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        std::fs::metadata(self.path(path)).and_then(|m| m.modified()).ok()
+    }
+}
+
+/// Files kept in memory, e.g. for tests or generated content.
+#[derive(Default)]
+pub struct MemoryMount {
+    files: HashMap<String, Arc<[u8]>>,
+}
+
+impl MemoryMount {
+    pub fn new() -> MemoryMount {
+        MemoryMount::default()
+    }
+
+    pub fn insert(&mut self, path: &str, data: impl Into<Vec<u8>>) {
+        let path = normalize(Path::new(path)).expect("invalid path for a memory mount");
+        self.files.insert(path, data.into().into());
+    }
+
+    pub fn with(mut self, path: &str, data: impl Into<Vec<u8>>) -> MemoryMount {
+        self.insert(path, data);
+        self
+    }
+}
+
+impl Mount for MemoryMount {
+    fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>> {
+        Ok(self.files.get(path).map(|data| Box::new(Cursor::new(data.clone())) as Box<dyn VfsFile>))
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+}
+
+const PAK_MAGIC: &[u8; 8] = b"BARFPAK\x01";
+
+// Pak format: the magic header, a u32 file count and one index entry per
+// file, each a u32 name length, the UTF-8 name and u64 offset and length of
+// the contents. The contents follow the index. All numbers little-endian.
+
+/// An archive made with `PakMount::build`. Files are read straight from the
+/// archive when opened, nothing is kept in memory except the index.
+pub struct PakMount {
+    path: PathBuf,
+    files: HashMap<String, (u64, u64)>,
+}
+
+impl PakMount {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<PakMount> {
+        let path = path.into();
+        let mut file = BufReader::new(File::open(&path)?);
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != PAK_MAGIC {
+            return Err(invalid("not a pak file"));
+        }
+        // lengths are checked against the size so a broken index can't make
+        // us allocate more than the file holds
+        let size = file.get_ref().metadata()?.len();
+        let count = read_u32(&mut file)?;
+        let mut files = HashMap::new();
+        for _ in 0..count {
+            let name_len = u64::from(read_u32(&mut file)?);
+            if name_len > size {
+                return Err(invalid("file name is longer than the pak"));
+            }
+            let mut name = vec![0; name_len as usize];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("file name is not valid UTF-8"))?;
+            let offset = read_u64(&mut file)?;
+            let len = read_u64(&mut file)?;
+            match offset.checked_add(len) {
+                Some(end) if end <= size => {}
+                _ => return Err(invalid("file extends past the end of the pak")),
+            }
+            files.insert(name, (offset, len));
+        }
+        Ok(PakMount { path, files })
+    }
+
+    /// Packs all files under `dir` into a new archive at `pak`.
+    pub fn build(dir: &Path, pak: &Path) -> io::Result<()> {
+        let mut files = Vec::new();
+        collect_files(dir, String::new(), &mut files)?;
+        files.sort();
+        let index_len: u64 = files.iter().map(|(name, _)| 4 + name.len() as u64 + 16).sum();
+        let mut offset = PAK_MAGIC.len() as u64 + 4 + index_len;
+        let mut out = BufWriter::new(File::create(pak)?);
+        out.write_all(PAK_MAGIC)?;
+        out.write_all(&(files.len() as u32).to_le_bytes())?;
+        for (name, path) in &files {
+            let len = std::fs::metadata(path)?.len();
+            out.write_all(&(name.len() as u32).to_le_bytes())?;
+            out.write_all(name.as_bytes())?;
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&len.to_le_bytes())?;
+            offset += len;
+        }
+        for (_, path) in &files {
+            io::copy(&mut File::open(path)?, &mut out)?;
+        }
+        out.flush()
+    }
+}
+
+impl Mount for PakMount {
+    fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>> {
+        let &(start, len) = match self.files.get(path) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut file = BufReader::new(File::open(&self.path)?);
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Some(Box::new(PakFile { file, start, len, pos: 0 })))
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+}
+
+/// A zip archive. Files are decompressed into memory when opened, stored
+/// and deflated entries are supported, zip64 and encryption aren't.
+#[cfg(feature = "zip")]
+pub struct ZipMount {
+    path: PathBuf,
+    files: HashMap<String, ZipEntry>,
+}
+
+#[cfg(feature = "zip")]
+struct ZipEntry {
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_len: u64,
+    len: u64,
+    header_offset: u64,
+}
+
+#[cfg(feature = "zip")]
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+#[cfg(feature = "zip")]
+const ZIP_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+#[cfg(feature = "zip")]
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+
+#[cfg(feature = "zip")]
+impl ZipMount {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<ZipMount> {
+        let path = path.into();
+        let mut file = BufReader::new(File::open(&path)?);
+        let size = file.get_ref().metadata()?.len();
+
+        // The end of central directory record is 22 bytes plus a comment of
+        // up to 64 KiB, search backwards for its signature.
+        let tail_len = size.min(22 + 0xffff);
+        file.seek(SeekFrom::Start(size - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        file.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|&i| u32_at(&tail, i) == ZIP_END_OF_DIRECTORY)
+            .ok_or_else(|| invalid_zip("not a zip file"))?;
+        let count = u16_at(&tail, end + 10);
+        let directory_len = u64::from(u32_at(&tail, end + 12));
+        let directory_offset = u64::from(u32_at(&tail, end + 16));
+        if directory_offset + directory_len > size {
+            return Err(invalid_zip("central directory extends past the end"));
+        }
+
+        file.seek(SeekFrom::Start(directory_offset))?;
+        let mut directory = vec![0; directory_len as usize];
+        file.read_exact(&mut directory)?;
+        let mut files = HashMap::new();
+        let mut pos = 0;
+        for _ in 0..count {
+            if pos + 46 > directory.len() || u32_at(&directory, pos) != ZIP_DIRECTORY_ENTRY {
+                return Err(invalid_zip("broken central directory"));
+            }
+            let name_len = usize::from(u16_at(&directory, pos + 28));
+            let extra_len = usize::from(u16_at(&directory, pos + 30));
+            let comment_len = usize::from(u16_at(&directory, pos + 32));
+            let name = directory.get((pos + 46)..(pos + 46 + name_len))
+                .ok_or_else(|| invalid_zip("broken central directory"))?;
+            let name = std::str::from_utf8(name)
+                .map_err(|_| invalid_zip("file name is not valid UTF-8"))?;
+            let entry = ZipEntry {
+                flags: u16_at(&directory, pos + 8),
+                method: u16_at(&directory, pos + 10),
+                crc: u32_at(&directory, pos + 16),
+                compressed_len: u64::from(u32_at(&directory, pos + 20)),
+                len: u64::from(u32_at(&directory, pos + 24)),
+                header_offset: u64::from(u32_at(&directory, pos + 42)),
+            };
+            if entry.compressed_len == 0xffff_ffff || entry.len == 0xffff_ffff {
+                return Err(invalid_zip("zip64 archives are not supported"));
+            }
+            if !name.ends_with('/') {
+                files.insert(normalize(Path::new(name))?, entry);
+            }
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(ZipMount { path, files })
+    }
+}
+
+#[cfg(feature = "zip")]
+impl Mount for ZipMount {
+    fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>> {
+        let entry = match self.files.get(path) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if entry.flags & 1 != 0 {
+            return Err(invalid_zip("encrypted files are not supported"));
+        }
+        let mut file = BufReader::new(File::open(&self.path)?);
+        let size = file.get_ref().metadata()?.len();
+        file.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0; 30];
+        file.read_exact(&mut header)?;
+        if u32_at(&header, 0) != ZIP_LOCAL_HEADER {
+            return Err(invalid_zip("broken local file header"));
+        }
+        // The local name and extra field can differ from the directory's.
+        let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+        let start = file.seek(SeekFrom::Current(skip))?;
+        if start + entry.compressed_len > size {
+            return Err(invalid_zip("file extends past the end of the zip"));
+        }
+        let mut compressed = vec![0; entry.compressed_len as usize];
+        file.read_exact(&mut compressed)?;
+        let data = match entry.method {
+            0 => compressed,
+            8 => inflate(&compressed, entry.len as usize)
+                .map_err(|e| invalid_zip(&format!("failed to inflate {}: {:?}", path, e)))?,
+            method => return Err(invalid_zip(&format!("unsupported compression method {}", method))),
+        };
+        if data.len() as u64 != entry.len || crc32fast::hash(&data) != entry.crc {
+            return Err(invalid_zip(&format!("{} is corrupt", path)));
+        }
+        Ok(Some(Box::new(Cursor::new(data))))
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+}
+
+/// Inflates raw deflate data that should come out as `len` bytes.
+#[cfg(feature = "zip")]
+fn inflate(compressed: &[u8], len: usize) -> Result<Vec<u8>, miniz_oxide::inflate::TINFLStatus> {
+    use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+    use miniz_oxide::inflate::TINFLStatus;
+
+    // one spare byte, so data longer than `len` shows up as a length mismatch
+    let mut data = vec![0; len + 1];
+    let flags = inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, _, written) = decompress(&mut DecompressorOxide::new(), compressed, &mut data, 0, flags);
+    match status {
+        TINFLStatus::Done | TINFLStatus::HasMoreOutput => {
+            data.truncate(written);
+            Ok(data)
+        }
+        status => Err(status),
+    }
+}
+
+#[cfg(feature = "zip")]
+fn invalid_zip(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid zip file: {}", message))
+}
+
+#[cfg(feature = "zip")]
+fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+#[cfg(feature = "zip")]
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+fn collect_files(dir: &Path, prefix: String, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "file name is not valid UTF-8"))?;
+        let name = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), name, files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// One file inside a pak, seeking stays within its bounds.
+struct PakFile {
+    file: BufReader<File>,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Read for PakFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.len - self.pos) as usize;
+        let max = buf.len().min(remaining);
+        let read = self.file.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for PakFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"));
+        }
+        let pos = (pos as u64).min(self.len);
+        self.file.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("barf-vfs-{}-{}", name, std::process::id()));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn read(vfs: &Vfs, path: &str) -> io::Result<String> {
+        Ok(String::from_utf8(vfs.read(Path::new(path))?).unwrap())
+    }
+
+    #[test]
+    fn normalize_paths() {
+        let normalized = |path: &str| normalize(Path::new(path)).ok();
+        assert_eq!(normalized("a/b.png").as_deref(), Some("a/b.png"));
+        assert_eq!(normalized("./a//b/./c").as_deref(), Some("a/b/c"));
+        assert_eq!(normalized("a\\b\\c").as_deref(), Some("a/b/c"));
+        assert_eq!(normalized("a/../b/c/..").as_deref(), Some("b"));
+        assert_eq!(normalized("").as_deref(), Some(""));
+        assert_eq!(normalized("../a"), None);
+        assert_eq!(normalized("a/../../b"), None);
+    }
+
+    #[test]
+    fn later_mounts_take_priority() {
+        let vfs = Vfs::new();
+        vfs.mount(MemoryMount::new().with("a.txt", "base a").with("b.txt", "base b"));
+        vfs.mount(MemoryMount::new().with("a.txt", "patch a").with("c/d.txt", "patch d"));
+        assert_eq!(read(&vfs, "a.txt").unwrap(), "patch a");
+        assert_eq!(read(&vfs, "./b.txt").unwrap(), "base b");
+        assert_eq!(read(&vfs, "c\\d.txt").unwrap(), "patch d");
+        assert_eq!(read(&vfs, "missing.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(read(&vfs, "../a.txt").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // clones share their mounts
+        let clone = vfs.clone();
+        vfs.mount(MemoryMount::new().with("b.txt", "mod b"));
+        assert_eq!(read(&clone, "b.txt").unwrap(), "mod b");
+    }
+
+    /// Counts how often files are opened.
+    struct CountingMount(MemoryMount, Arc<AtomicUsize>);
+
+    impl Mount for CountingMount {
+        fn open(&self, path: &str) -> io::Result<Option<Box<dyn VfsFile>>> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.open(path)
+        }
+
+        fn contains(&self, path: &str) -> bool {
+            self.0.contains(path)
+        }
+
+        fn modified(&self, _path: &str) -> Option<SystemTime> {
+            Some(SystemTime::UNIX_EPOCH)
+        }
+    }
+
+    #[test]
+    fn modified_does_not_open_files() {
+        let opens = Arc::new(AtomicUsize::new(0));
+        let vfs = Vfs::new();
+        vfs.mount(CountingMount(MemoryMount::new().with("a.txt", "a"), opens.clone()));
+        vfs.mount(MemoryMount::new().with("b.txt", "b"));
+        assert_eq!(vfs.modified(Path::new("a.txt")), Some(SystemTime::UNIX_EPOCH));
+        assert_eq!(vfs.modified(Path::new("b.txt")), None);
+        assert_eq!(vfs.modified(Path::new("c.txt")), None);
+        assert_eq!(opens.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn file_system_paths() {
+        let dir = TempDir::new("fs");
+        std::fs::write(dir.0.join("a.txt"), "on disk").unwrap();
+        let vfs = Vfs::new();
+        vfs.mount(MemoryMount::new().with("a.txt", "in memory"));
+        let absolute = dir.0.join("a.txt");
+        assert_eq!(read(&vfs, absolute.to_str().unwrap()).unwrap(), "on disk");
+        assert!(vfs.modified(&absolute).is_some());
+
+        vfs.mount(DirMount::new(&dir.0));
+        assert_eq!(read(&vfs, "a.txt").unwrap(), "on disk");
+        assert!(vfs.modified(Path::new("a.txt")).is_some());
+    }
+
+    #[test]
+    fn pak_round_trip() {
+        let dir = TempDir::new("pak");
+        let src = dir.0.join("src");
+        std::fs::create_dir_all(src.join("sprites")).unwrap();
+        std::fs::write(src.join("a.txt"), "first file").unwrap();
+        std::fs::write(src.join("sprites/b.bin"), [0u8, 1, 2, 3, 4, 5, 6, 7]).unwrap();
+        std::fs::write(src.join("empty"), "").unwrap();
+        let pak = dir.0.join("data.pak");
+        PakMount::build(&src, &pak).unwrap();
+
+        let vfs = Vfs::new();
+        vfs.mount(PakMount::open(&pak).unwrap());
+        assert_eq!(read(&vfs, "a.txt").unwrap(), "first file");
+        assert_eq!(read(&vfs, "empty").unwrap(), "");
+        assert_eq!(read(&vfs, "sprites").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let mut file = vfs.open(Path::new("sprites/b.bin")).unwrap();
+        let mut buf = [0; 3];
+        assert_eq!(file.seek(SeekFrom::End(-3)).unwrap(), 5);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [5, 6, 7]);
+        assert_eq!(file.read(&mut buf).unwrap(), 0, "reads stop at the end of the file");
+        assert_eq!(file.seek(SeekFrom::Current(-6)).unwrap(), 2);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3, 4]);
+        assert!(file.seek(SeekFrom::Current(-10)).is_err());
+        assert_eq!(file.seek(SeekFrom::Start(100)).unwrap(), 8);
+    }
+
+    #[test]
+    fn broken_paks() {
+        let dir = TempDir::new("broken-pak");
+        let pak = dir.0.join("broken.pak");
+        let open = |contents: &[u8]| {
+            std::fs::write(&pak, contents).unwrap();
+            PakMount::open(&pak).err().map(|e| e.kind())
+        };
+        let mut huge_name = PAK_MAGIC.to_vec();
+        huge_name.extend_from_slice(&1u32.to_le_bytes());
+        huge_name.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(open(&huge_name), Some(io::ErrorKind::InvalidData));
+
+        let mut past_end = PAK_MAGIC.to_vec();
+        past_end.extend_from_slice(&1u32.to_le_bytes());
+        past_end.extend_from_slice(&1u32.to_le_bytes());
+        past_end.push(b'a');
+        past_end.extend_from_slice(&0u64.to_le_bytes());
+        past_end.extend_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(open(&past_end), Some(io::ErrorKind::InvalidData));
+
+        assert_eq!(open(b"BARFPAK"), Some(io::ErrorKind::UnexpectedEof));
+        assert_eq!(open(b"NOTAPAK\x01\0\0\0\0"), Some(io::ErrorKind::InvalidData));
+    }
+
+    /// Writes a zip with the given entries, `true` for deflated ones.
+    #[cfg(feature = "zip")]
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, data, deflate) in entries {
+            let compressed = if deflate {
+                miniz_oxide::deflate::compress_to_vec(data, 6)
+            } else {
+                data.to_vec()
+            };
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&(if deflate { 8u16 } else { 0 }).to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            fields.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+            fields.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+            directory.extend_from_slice(&ZIP_DIRECTORY_ENTRY.to_le_bytes());
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            out.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+            out.extend_from_slice(&[20, 0, 0, 0]);
+            out.extend_from_slice(&fields);
+            out.extend_from_slice(&[0; 2]);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&compressed);
+        }
+        let directory_offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&ZIP_END_OF_DIRECTORY.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(b"\x05\x00hello");
+        out
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_archives() {
+        let dir = TempDir::new("zip");
+        let path = dir.0.join("data.zip");
+        let text = "squeeze me ".repeat(100);
+        std::fs::write(&path, zip(&[
+            ("stored.txt", b"as is", false),
+            ("levels/", b"", false),
+            ("levels/1.txt", text.as_bytes(), true),
+        ]))
+        .unwrap();
+
+        let vfs = Vfs::new();
+        vfs.mount(ZipMount::open(&path).unwrap());
+        assert_eq!(read(&vfs, "stored.txt").unwrap(), "as is");
+        assert_eq!(read(&vfs, "levels/1.txt").unwrap(), text);
+        assert_eq!(read(&vfs, "levels").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let mut corrupt = zip(&[("a.txt", b"some text", false)]);
+        let data = corrupt.windows(9).position(|w| w == b"some text").unwrap();
+        corrupt[data] = b'S';
+        std::fs::write(&path, corrupt).unwrap();
+        let vfs = Vfs::new();
+        vfs.mount(ZipMount::open(&path).unwrap());
+        assert_eq!(read(&vfs, "a.txt").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        std::fs::write(&path, "not a zip").unwrap();
+        assert!(ZipMount::open(&path).is_err());
+    }
+}
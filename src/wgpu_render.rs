@@ -1,10 +1,14 @@
 use std::{
-    cmp::Ordering,
+    future::Future,
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
 };
 use wgpu::util::DeviceExt;
 use crate::texture;
 use crate::Texture as TextureIndex;
+use crate::{Config, PowerPreference, RunError};
 
 macro_rules! include_spirv {
     ($name:tt) => {{
@@ -60,6 +64,8 @@ unsafe fn as_bytes<T>(slice: &[T]) -> &[u8] {
 
 pub(crate) struct Instance {
     pub(crate) texture: TextureIndex,
+    /// Overlay instances are drawn after everything else.
+    pub(crate) overlay: bool,
     pub(crate) raw: RawInstance,
 }
 
@@ -125,6 +131,125 @@ fn cast_instances(instances: &[RawInstance]) -> &[u8] {
     unsafe { as_bytes(instances) }
 }
 
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// Measures how long the GPU spends on a frame using timestamp queries.
+/// Results arrive a few frames late, frames are skipped while waiting.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    buffer: wgpu::Buffer,
+    mapping: Option<MapFuture>,
+    period: f32,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, period: f32) -> GpuTimer {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Buffer"),
+            size: 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        GpuTimer {
+            query_set,
+            buffer,
+            mapping: None,
+            period,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.mapping.is_none()
+    }
+
+    fn begin(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    fn end(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.buffer, 0);
+    }
+
+    fn start_readback(&mut self) {
+        self.mapping = Some(Box::pin(self.buffer.slice(..).map_async(wgpu::MapMode::Read)));
+    }
+
+    /// Returns the measured duration once the readback has finished.
+    fn poll(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let mapping = self.mapping.as_mut()?;
+        device.poll(wgpu::Maintain::Poll);
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        let result = match mapping.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        self.mapping = None;
+        result.ok()?;
+        let elapsed = {
+            let data = self.buffer.slice(..).get_mapped_range();
+            let mut start = [0; 8];
+            let mut end = [0; 8];
+            start.copy_from_slice(&data[0..8]);
+            end.copy_from_slice(&data[8..16]);
+            u64::from_ne_bytes(end).wrapping_sub(u64::from_ne_bytes(start))
+        };
+        self.buffer.unmap();
+        Some(Duration::from_nanos((elapsed as f64 * f64::from(self.period)) as u64))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RenderStats {
+    pub(crate) instances: usize,
+    pub(crate) batches: usize,
+    pub(crate) gpu_time: Option<Duration>,
+}
+
+/// Finds an adapter that can draw to the window, trying every power
+/// preference and, unless one was chosen, every backend if allowed by
+/// `Config::fallback_adapter`.
+async fn request_adapter(
+    window: &winit::window::Window,
+    config: &Config,
+) -> Result<(wgpu::Surface, wgpu::Adapter), RunError> {
+    // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
+    let backends = config.backend.map_or(wgpu::BackendBit::PRIMARY, Into::into);
+    let mut attempts = vec![(backends, config.power_preference)];
+    if config.fallback_adapter {
+        let other = match config.power_preference {
+            PowerPreference::LowPower => PowerPreference::HighPerformance,
+            PowerPreference::HighPerformance => PowerPreference::LowPower,
+        };
+        match config.backend {
+            Some(_) => attempts.push((backends, other)),
+            None => {
+                attempts.push((wgpu::BackendBit::all(), config.power_preference));
+                attempts.push((wgpu::BackendBit::all(), other));
+            }
+        }
+    }
+    for (backends, power_preference) in attempts {
+        // The instance is a handle to our GPU
+        let instance = wgpu::Instance::new(backends);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: power_preference.into(),
+                compatible_surface: Some(&surface),
+            },
+        ).await;
+        if let Some(adapter) = adapter {
+            return Ok((surface, adapter));
+        }
+    }
+    Err(RunError::NoAdapter)
+}
+
 pub(crate) struct WgpuState {
     surface: wgpu::Surface,
     pub(crate) device: Arc<wgpu::Device>,
@@ -141,31 +266,32 @@ pub(crate) struct WgpuState {
     depth_texture: texture::Texture,
     pub(crate) textures: Vec<texture::BindableTexture>,
     pub(crate) instances: Vec<Instance>,
+    gpu_timer: Option<GpuTimer>,
+    pub(crate) stats: RenderStats,
 }
 
 impl WgpuState {
-    pub(crate) async fn new(window: &winit::window::Window) -> WgpuState {
+    pub(crate) async fn new(
+        window: &winit::window::Window,
+        config: &Config,
+    ) -> Result<WgpuState, RunError> {
         let size = window.inner_size();
+        let (surface, adapter) = request_adapter(window, config).await?;
 
-        // The instance is a handle to our GPU
-        // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-            },
-        ).await.unwrap();
-
+        let features = adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
+                features,
                 limits: wgpu::Limits::default(),
                 label: None,
             },
             None,
-        ).await.unwrap();
+        ).await.map_err(RunError::RequestDevice)?;
+        let gpu_timer = if features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            Some(GpuTimer::new(&device, adapter.get_timestamp_period()))
+        } else {
+            None
+        };
         let device = Arc::new(device);
         let queue = Arc::new(queue);
 
@@ -174,7 +300,7 @@ impl WgpuState {
             format: adapter.get_swap_chain_preferred_format(&surface),
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: config.present_mode.into(),
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
@@ -220,7 +346,7 @@ impl WgpuState {
             1,
             1,
             None,
-            &[],
+            &texture::TextureProcessors::new(),
         );
         let pixel_texture = texture::BindableTexture::from_texture(
             pixel_texture,
@@ -301,7 +427,7 @@ impl WgpuState {
             }
         );
 
-        Self {
+        Ok(Self {
             surface,
             device,
             queue,
@@ -317,7 +443,9 @@ impl WgpuState {
             instance_buffer,
             depth_texture,
             textures: vec![pixel_texture],
-        }
+            gpu_timer,
+            stats: RenderStats::default(),
+        })
     }
 
     pub(crate) fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -346,27 +474,23 @@ impl WgpuState {
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
-        self.instances.sort_by_key(|i| i.texture.index);
+        self.instances.sort_by_key(|i| (i.overlay, i.texture.index));
         self.raw_instances.clear();
         self.raw_instances.extend(self.instances.iter().map(|i| i.raw));
         self.queue.write_buffer(&self.instance_buffer, 0, cast_instances(&self.raw_instances));
-        let mut idx = 0;
-        while idx < self.instances.len() {
-            let texture = self.instances[idx].texture;
-            let count = match self.instances[idx..].binary_search_by(|item| {
-                if item.texture.index <= texture.index {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                }
-            }) {
-                Ok(i) => i,
-                Err(i) => i,
-            };
-            assert!(count > 0);
-            let instance_range = (idx as u32)..((idx + count) as u32);
-            idx += count;
 
+        if let Some(timer) = &mut self.gpu_timer {
+            if let Some(gpu_time) = timer.poll(&self.device) {
+                self.stats.gpu_time = Some(gpu_time);
+            }
+        }
+        let measure_gpu = matches!(&self.gpu_timer, Some(timer) if timer.is_idle());
+        if measure_gpu {
+            self.gpu_timer.as_ref().unwrap().begin(&mut encoder);
+        }
+
+        let mut batches = 0;
+        {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[
@@ -395,13 +519,35 @@ impl WgpuState {
             });
 
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.textures[texture.index].bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..(RENDERER_INDICES.len() as u32), 0, instance_range);
-        };
+
+            let mut idx = 0;
+            while idx < self.instances.len() {
+                let texture = self.instances[idx].texture;
+                let count = self.instances[idx..]
+                    .iter()
+                    .take_while(|i| i.texture.index == texture.index)
+                    .count();
+                let instance_range = (idx as u32)..((idx + count) as u32);
+                idx += count;
+                batches += 1;
+
+                render_pass.set_bind_group(0, &self.textures[texture.index].bind_group, &[]);
+                render_pass.draw_indexed(0..(RENDERER_INDICES.len() as u32), 0, instance_range);
+            }
+        }
+
+        if measure_gpu {
+            self.gpu_timer.as_ref().unwrap().end(&mut encoder);
+        }
+        self.stats.instances = self.instances.len();
+        self.stats.batches = batches;
         self.queue.submit(std::iter::once(encoder.finish()));
+        if measure_gpu {
+            self.gpu_timer.as_mut().unwrap().start_readback();
+        }
         Ok(())
     }
 }
@@ -0,0 +1,71 @@
+use std::{error::Error, path::Path};
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    window::{self, Icon, Window, WindowBuilder},
+};
+use crate::{Fullscreen, RunError};
+
+/// Window changes requested through `Ctx`, applied after the frame is drawn.
+pub(crate) enum WindowCommand {
+    SetTitle(String),
+    SetFullscreen(Option<Fullscreen>),
+}
+
+pub(crate) fn build(
+    config: &crate::Config,
+    event_loop: &EventLoop<()>,
+) -> Result<Window, RunError> {
+    let mut builder = WindowBuilder::new()
+        .with_title(&config.title)
+        .with_resizable(config.resizable);
+    if let Some((width, height)) = config.size {
+        builder = builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    if let Some((width, height)) = config.min_size {
+        builder = builder.with_min_inner_size(PhysicalSize::new(width, height));
+    }
+    if let Some((width, height)) = config.max_size {
+        builder = builder.with_max_inner_size(PhysicalSize::new(width, height));
+    }
+    if let Some(path) = &config.icon {
+        let icon = load_icon(path).map_err(|e| RunError::LoadIcon(path.clone(), e))?;
+        builder = builder.with_window_icon(Some(icon));
+    }
+    let window = builder.build(event_loop).map_err(RunError::CreateWindow)?;
+    if config.fullscreen.is_some() {
+        set_fullscreen(&window, config.fullscreen);
+    }
+    Ok(window)
+}
+
+fn load_icon(path: &Path) -> Result<Icon, Box<dyn Error + Send + Sync>> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Icon::from_rgba(image.into_raw(), width, height)?)
+}
+
+pub(crate) fn set_fullscreen(window: &Window, fullscreen: Option<Fullscreen>) {
+    let fullscreen = match fullscreen {
+        None => None,
+        Some(Fullscreen::Borderless) => {
+            Some(window::Fullscreen::Borderless(window.current_monitor()))
+        }
+        Some(Fullscreen::Exclusive) => {
+            let mode = window.current_monitor()
+                .or_else(|| window.primary_monitor())
+                .and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|mode| {
+                        let size = mode.size();
+                        (size.width * size.height, mode.refresh_rate(), mode.bit_depth())
+                    })
+                });
+            match mode {
+                Some(mode) => Some(window::Fullscreen::Exclusive(mode)),
+                // No video modes to pick from, e.g. on the web.
+                None => Some(window::Fullscreen::Borderless(None)),
+            }
+        }
+    };
+    window.set_fullscreen(fullscreen);
+}